@@ -0,0 +1,37 @@
+# Completion of `cargo add`, `cargo rm` and `cargo upgrade` through cargo_edit_completion.
+# Other cargo subcommands are handed to the completion function that was already registered.
+
+_cargo_edit_completion_previous=$(complete -p cargo 2>/dev/null | sed -n 's/.*-F \([^ ]*\).*/\1/p')
+
+_cargo_edit_completion() {
+  case "${COMP_WORDS[1]}" in
+    add | rm | remove | upgrade)
+      local IFS=$'\n'
+      COMPREPLY=($(COMP_LINE="$COMP_LINE" COMP_POINT="$COMP_POINT" cargo_edit_completion --quiet bash))
+      ;;
+    *)
+      if [[ -n "$_cargo_edit_completion_previous" ]]; then
+        "$_cargo_edit_completion_previous" "$@"
+      fi
+      ;;
+  esac
+}
+
+complete -o default -F _cargo_edit_completion cargo
+
+# Remember the crates and features of each `cargo add` run, so they are listed first next time.
+_cargo_edit_completion_last=$(HISTTIMEFORMAT= history 1)
+
+_cargo_edit_completion_record() {
+  local entry line
+  entry=$(HISTTIMEFORMAT= history 1)
+  # Commands repeated or not saved leave the last history entry as it was.
+  [[ "$entry" == "$_cargo_edit_completion_last" ]] && return
+  _cargo_edit_completion_last=$entry
+  read -r _ line <<<"$entry"
+  if [[ "$line" == "cargo add "* ]]; then
+    cargo_edit_completion --quiet record --line "$line" &>/dev/null
+  fi
+}
+
+PROMPT_COMMAND="_cargo_edit_completion_record${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
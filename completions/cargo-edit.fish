@@ -0,0 +1,14 @@
+# Completion of `cargo add`, `cargo rm` and `cargo upgrade` through cargo_edit_completion.
+
+function __cargo_edit_completion
+    set -l line (commandline -cp)
+    COMP_LINE="$line" cargo_edit_completion --quiet bash
+end
+
+complete -c cargo -n '__fish_seen_subcommand_from add rm remove upgrade' -f -a '(__cargo_edit_completion)'
+
+# Remember the crates and features of each `cargo add` run, so they are listed first next time.
+function __cargo_edit_completion_record --on-event fish_preexec
+    string match -q 'cargo add *' -- $argv[1]
+    and cargo_edit_completion --quiet record --line $argv[1] &>/dev/null
+end
@@ -0,0 +1,88 @@
+use std::iter;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::Parser;
+
+use cargo_edit_completion_lib::backend::{CachedIndex, IndexBackend};
+use cargo_edit_completion_lib::config::CargoConfig;
+use cargo_edit_completion_lib::line::split_words;
+use cargo_edit_completion_lib::Options;
+
+use crate::{complete_indexed, open_index, options, Mode, Opts};
+
+/// Queries timed unless others are given, from short prefixes matching many crates to the
+/// versions and features of a single one.
+const QUERIES: &[&str] = &[
+    "crate s",
+    "crate ser",
+    "crate serde",
+    "crate tokio-",
+    "crate serde@1",
+    "feature serde@1",
+];
+
+/// Time completing a query like `crate tok`, draining all of its candidates.
+fn time(
+    index: &dyn IndexBackend,
+    config: &CargoConfig,
+    options: &Options,
+    query: &str,
+) -> Result<Duration> {
+    let mode = Mode::try_parse_from(iter::once(env!("CARGO_PKG_NAME")).chain(split_words(query)))?;
+    let start = Instant::now();
+    // Failing, e.g. for a crate missing from the index, takes time as well.
+    let _ = complete_indexed(mode, index, config, options).map(Iterator::count);
+    Ok(start.elapsed())
+}
+
+/// Percentiles of timings sorted from fastest to slowest.
+fn percentile(timings: &[Duration], percent: usize) -> Duration {
+    timings[(timings.len() - 1) * percent / 100]
+}
+
+fn summary(mut timings: Vec<Duration>) -> String {
+    timings.sort();
+    [50, 90, 100]
+        .iter()
+        .map(|&percent| {
+            let millis = percentile(&timings, percent).as_secs_f64() * 1000.0;
+            format!("{:>9.2}ms", millis)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Time each query `runs` times against the index the options select, printing percentiles.
+///
+/// Cold runs open the index anew like each invocation does, warm ones reuse one keeping its
+/// answers in memory like the daemon does.
+pub fn bench(opts: &Opts, config: &CargoConfig, queries: &[String], runs: usize) -> Result<()> {
+    let queries: Vec<&str> = if queries.is_empty() {
+        QUERIES.to_vec()
+    } else {
+        queries.iter().map(String::as_str).collect()
+    };
+    let options = options(opts, config)?;
+    let runs = runs.max(1);
+    println!(
+        "{:<20} {:>11} {:>11} {:>11} {:>11} {:>11} {:>11}",
+        "query", "cold p50", "p90", "max", "warm p50", "p90", "max"
+    );
+    for query in queries {
+        let cold = (0..runs)
+            .map(|_| {
+                let start = Instant::now();
+                let index = open_index(opts, config)?;
+                Ok(start.elapsed() + time(&index, config, &options, query)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let index = CachedIndex::new(open_index(opts, config)?);
+        time(&index, config, &options, query)?;
+        let warm = (0..runs)
+            .map(|_| time(&index, config, &options, query))
+            .collect::<Result<Vec<_>>>()?;
+        println!("{:<20} {} {}", query, summary(cold), summary(warm));
+    }
+    Ok(())
+}
@@ -0,0 +1,499 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use clap::{ArgSettings::TakesValue, FromArgMatches, IntoApp};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use cargo_edit_completion_lib::backend::{CachedIndex, IndexBackend};
+use cargo_edit_completion_lib::cache;
+use cargo_edit_completion_lib::config::CargoConfig;
+use cargo_edit_completion_lib::deadline::Deadline;
+use cargo_edit_completion_lib::Candidate;
+
+use crate::settings::{Settings, CACHE_DIR_VAR};
+use crate::{complete_indexed, index_key, open_index, options, set_network_policy, Opts};
+
+/// Prefix of the environment variables requests carry along: cargo's, overriding its
+/// configuration, and the `CARGO_EDIT_COMPLETION_*` ones overriding options.
+const ENV_PREFIX: &str = "CARGO_";
+
+/// A command line to complete as if run in `cwd` with the `CARGO_*` variables of `env`.
+#[derive(Serialize, Deserialize)]
+struct Request {
+    args: Vec<String>,
+    cwd: PathBuf,
+    #[serde(default)]
+    env: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    candidates: Option<Vec<Candidate>>,
+    error: Option<String>,
+    /// Whether `--timeout-ms` cut the search short.
+    #[serde(default)]
+    partial: bool,
+}
+
+/// Values of a flag's variable turning it off, the same clap knows.
+const FALSE_LITERALS: [&str; 6] = ["n", "no", "f", "false", "off", "0"];
+
+/// How long to wait for the daemon without `--timeout-ms`, before completing without it.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client may take to send its request, before the daemon hangs up to serve the next.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Indexes opened so far along with their state when opened, by the options and registry
+/// selecting them.
+type Indexes = HashMap<String, (Option<String>, Box<dyn IndexBackend>)>;
+
+/// Socket the daemon listens on unless told otherwise.
+#[cfg(unix)]
+pub fn default_socket() -> Option<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => {
+            Some(PathBuf::from(dir).join(concat!(env!("CARGO_PKG_NAME"), ".sock")))
+        }
+        _ => Some(cache::cache_dir()?.join("daemon.sock")),
+    }
+}
+
+/// Named pipe the daemon listens on unless told otherwise, one per user.
+#[cfg(windows)]
+pub fn default_socket() -> Option<PathBuf> {
+    let user = env::var("USERNAME").unwrap_or_default();
+    Some(PathBuf::from(format!(
+        r"\\.\pipe\{}-{}",
+        env!("CARGO_PKG_NAME"),
+        user
+    )))
+}
+
+fn request() -> Option<Request> {
+    Some(Request {
+        args: env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect(),
+        cwd: env::current_dir().ok()?,
+        env: env::vars()
+            .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+            .collect(),
+    })
+}
+
+/// Parse the options of a request as its own process would, with the variables of `vars`
+/// rather than those of the daemon.
+///
+/// Clap only reads the environment of this process, so the variables are passed as the options
+/// they stand for unless the command line gives those.
+fn parse_opts(args: &[String], vars: &[(String, String)]) -> Result<Opts> {
+    let env_args: Vec<_> = Opts::into_app()
+        .get_arguments()
+        .filter_map(|arg| {
+            let var = arg.get_env()?.to_str()?.to_string();
+            let long = arg.get_long()?.to_string();
+            Some((
+                arg.get_name().to_string(),
+                var,
+                long,
+                arg.is_set(TakesValue),
+            ))
+        })
+        .collect();
+    let mut app = Opts::into_app();
+    for (name, _, _, _) in &env_args {
+        // Looking up a variable with an empty name finds none, forgetting the value clap read
+        // from the environment of the daemon.
+        app = app.mut_arg(name.as_str(), |arg| arg.env_os(OsStr::new("")));
+    }
+    let given = app.clone().try_get_matches_from(args)?;
+    let mut args = args.to_vec();
+    for (name, var, long, takes_value) in &env_args {
+        let value = match vars.iter().find(|(key, _)| key == var) {
+            Some((_, value)) if given.occurrences_of(name.as_str()) == 0 => value,
+            _ => continue,
+        };
+        // Right after the program name, so before the mode.
+        let position = 1.min(args.len());
+        if *takes_value {
+            args.insert(position, format!("--{}={}", long, value));
+        } else if !FALSE_LITERALS.contains(&value.to_lowercase().as_str()) {
+            args.insert(position, format!("--{}", long));
+        }
+    }
+    let matches = app.try_get_matches_from(&args)?;
+    Opts::from_arg_matches(&matches).ok_or_else(|| anyhow!("failed to parse the options"))
+}
+
+fn answer(line: &str, indexes: &mut Indexes) -> Result<(Vec<Candidate>, bool)> {
+    let start = Instant::now();
+    let request: Request = serde_json::from_str(line)?;
+    let mut opts = parse_opts(&request.args, &request.env)?;
+    // Relative paths are relative to the directory of the client.
+    for path in [
+        &mut opts.config,
+        &mut opts.index_path,
+        &mut opts.advisory_db,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *path = request.cwd.join(&*path);
+    }
+    let config = CargoConfig::load_from(&request.cwd, request.env.iter().cloned())?;
+    let cache_dir = request.env.iter().find(|(key, _)| key == CACHE_DIR_VAR);
+    Settings::load(opts.config.as_deref(), &config)?
+        .apply(&mut opts, cache_dir.map(|(_, dir)| OsStr::new(dir)));
+    // Set even when unset, so a request doesn't keep the directory of the last.
+    cache::set_cache_dir(opts.cache_dir.clone());
+    let mode = opts.mode.take().ok_or_else(|| anyhow!("no mode given"))?;
+    set_network_policy(&opts, &config);
+    let key = index_key(&opts, &config);
+    let (state, index) = match indexes.entry(key) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let index = open_index(&opts, &config)?;
+            entry.insert((index.state(), Box::new(CachedIndex::new(index))))
+        }
+    };
+    // Forget what the index answered before it changed.
+    let current = index.state();
+    if *state != current {
+        debug!("reopening changed index");
+        *state = current;
+        *index = Box::new(CachedIndex::new(open_index(&opts, &config)?));
+    }
+    let options = options(&opts, &config)?;
+    let candidates = complete_indexed(mode, &**index, &config, &options)?
+        .take(opts.limit.unwrap_or(usize::MAX))
+        .collect();
+    let partial = options
+        .deadline
+        .is_some_and(|deadline| deadline.cut_short());
+    info!(args = ?request.args, elapsed = ?start.elapsed(), "answered");
+    Ok((candidates, partial))
+}
+
+/// Answer the requests of a client, one JSON line each, until it hangs up or `lines` ends.
+fn connection(
+    lines: impl Iterator<Item = io::Result<String>>,
+    mut out: impl Write,
+    indexes: &mut Indexes,
+) -> Result<()> {
+    for line in lines {
+        let response = match answer(&line?, indexes) {
+            Ok((candidates, partial)) => Response {
+                candidates: Some(candidates),
+                error: None,
+                partial,
+            },
+            Err(err) => Response {
+                candidates: None,
+                error: Some(err.to_string()),
+                partial: false,
+            },
+        };
+        writeln!(out, "{}", serde_json::to_string(&response)?)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// How long to wait for the daemon to answer, `None` if `deadline` already passed.
+fn answer_timeout(deadline: Option<&Deadline>) -> Option<Duration> {
+    match deadline.and_then(Deadline::remaining) {
+        Some(remaining) if remaining.is_zero() => None,
+        Some(remaining) => Some(remaining),
+        None => Some(ANSWER_TIMEOUT),
+    }
+}
+
+/// Ask the daemon to complete the command line of this process, cutting `deadline` short if the
+/// daemon did.
+fn ask(
+    input: impl Read,
+    mut out: impl Write,
+    deadline: Option<&Deadline>,
+) -> Option<Vec<Candidate>> {
+    writeln!(out, "{}", serde_json::to_string(&request()?).ok()?).ok()?;
+    out.flush().ok()?;
+    let mut line = String::new();
+    BufReader::new(input).read_line(&mut line).ok()?;
+    let response = serde_json::from_str::<Response>(&line).ok()?;
+    if let (true, Some(deadline)) = (response.partial, deadline) {
+        deadline.cut();
+    }
+    response.candidates
+}
+
+#[cfg(unix)]
+mod transport {
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+
+    use anyhow::{bail, Result};
+
+    use cargo_edit_completion_lib::deadline::Deadline;
+    use cargo_edit_completion_lib::Candidate;
+
+    use super::{answer_timeout, ask, connection, Indexes, REQUEST_TIMEOUT};
+
+    /// Answer completions over a socket, keeping indexes open and their answers in memory.
+    pub fn run(path: &Path) -> Result<()> {
+        if UnixStream::connect(path).is_ok() {
+            bail!("a daemon already listens on {}", path.display());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Left behind by a daemon that was killed.
+        let _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        let mut indexes = Indexes::new();
+        for stream in listener.incoming().flatten() {
+            // A client hanging up early, or keeping silent, only ends its connection.
+            if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err() {
+                continue;
+            }
+            if let Ok(input) = stream.try_clone() {
+                let _ = connection(BufReader::new(input).lines(), stream, &mut indexes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Complete the command line of this process with the daemon, if one listens on `path` and
+    /// answers in time.
+    pub fn query(path: &Path, deadline: Option<&Deadline>) -> Option<Vec<Candidate>> {
+        let timeout = answer_timeout(deadline)?;
+        let stream = UnixStream::connect(path).ok()?;
+        stream.set_read_timeout(Some(timeout)).ok()?;
+        stream.set_write_timeout(Some(timeout)).ok()?;
+        ask(stream.try_clone().ok()?, stream, deadline)
+    }
+}
+
+#[cfg(windows)]
+mod transport {
+    use std::ffi::OsStr;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufRead, BufReader};
+    use std::iter;
+    use std::mem;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use std::path::Path;
+    use std::ptr;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use anyhow::{bail, Context, Result};
+    use windows_sys::Win32::Foundation::{
+        BOOL, ERROR_PIPE_CONNECTED, GENERIC_ALL, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Security::{
+        AddAccessAllowedAce, GetLengthSid, GetTokenInformation, InitializeAcl,
+        InitializeSecurityDescriptor, SetSecurityDescriptorDacl, TokenUser, ACCESS_ALLOWED_ACE,
+        ACL, ACL_REVISION, SECURITY_ATTRIBUTES, SECURITY_DESCRIPTOR, TOKEN_QUERY, TOKEN_USER,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+    use windows_sys::Win32::System::SystemServices::SECURITY_DESCRIPTOR_REVISION;
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    use cargo_edit_completion_lib::deadline::Deadline;
+    use cargo_edit_completion_lib::Candidate;
+
+    use super::{answer_timeout, ask, connection, Indexes, REQUEST_TIMEOUT};
+
+    const BUFFER_SIZE: u32 = 64 * 1024;
+
+    fn open(path: &Path) -> io::Result<File> {
+        OpenOptions::new().read(true).write(true).open(path)
+    }
+
+    /// Fail with the error of the last call unless `ok` tells it succeeded.
+    fn check(ok: BOOL) -> io::Result<()> {
+        match ok {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Security attributes letting only the user running the daemon open the pipe, as other
+    /// users could otherwise read its answers or have it read their paths.
+    struct OwnerOnly {
+        attributes: SECURITY_ATTRIBUTES,
+        // Pointed to by `attributes`, so kept alive with them.
+        _descriptor: Box<SECURITY_DESCRIPTOR>,
+        _acl: Vec<u32>,
+    }
+
+    impl OwnerOnly {
+        fn new() -> io::Result<Self> {
+            let mut token = ptr::null_mut();
+            // SAFETY: the pseudo handle of the current process is always valid.
+            check(unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) })?;
+            // SAFETY: the token was just opened and is closed by nothing else.
+            let token = unsafe { OwnedHandle::from_raw_handle(token as _) };
+            let mut len = 0;
+            // SAFETY: with no buffer this only tells the length the user needs, failing.
+            unsafe {
+                GetTokenInformation(
+                    token.as_raw_handle() as _,
+                    TokenUser,
+                    ptr::null_mut(),
+                    0,
+                    &mut len,
+                )
+            };
+            // Of `u64`s to be aligned for the pointer `TOKEN_USER` starts with.
+            let mut user = vec![0u64; (len as usize).div_ceil(8)];
+            // SAFETY: the buffer holds `len` bytes.
+            check(unsafe {
+                GetTokenInformation(
+                    token.as_raw_handle() as _,
+                    TokenUser,
+                    user.as_mut_ptr().cast(),
+                    len,
+                    &mut len,
+                )
+            })?;
+            // SAFETY: the buffer was filled with a `TOKEN_USER`, its SID stored after it.
+            let sid = unsafe { (*user.as_ptr().cast::<TOKEN_USER>()).User.Sid };
+
+            // The ACE ends with the first field of the SID it holds.
+            // SAFETY: `sid` is valid while `user` lives.
+            let acl_len = mem::size_of::<ACL>() + mem::size_of::<ACCESS_ALLOWED_ACE>()
+                - mem::size_of::<u32>()
+                + unsafe { GetLengthSid(sid) } as usize;
+            let mut acl = vec![0u32; acl_len.div_ceil(4)];
+            let acl_ptr = acl.as_mut_ptr().cast::<ACL>();
+            // SAFETY: the buffer is as large as told, and the ACE copies the SID.
+            check(unsafe { InitializeAcl(acl_ptr, (acl.len() * 4) as u32, ACL_REVISION) })?;
+            check(unsafe { AddAccessAllowedAce(acl_ptr, ACL_REVISION, GENERIC_ALL, sid) })?;
+
+            // SAFETY: all-zero is a valid value of the plain struct, initialized right after.
+            let mut descriptor = Box::new(unsafe { mem::zeroed::<SECURITY_DESCRIPTOR>() });
+            let descriptor_ptr = (&mut *descriptor as *mut SECURITY_DESCRIPTOR).cast();
+            // SAFETY: the descriptor and the ACL it points to both live as long as `Self`.
+            check(unsafe {
+                InitializeSecurityDescriptor(descriptor_ptr, SECURITY_DESCRIPTOR_REVISION)
+            })?;
+            check(unsafe { SetSecurityDescriptorDacl(descriptor_ptr, 1, acl_ptr, 0) })?;
+            Ok(OwnerOnly {
+                attributes: SECURITY_ATTRIBUTES {
+                    nLength: mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                    lpSecurityDescriptor: descriptor_ptr,
+                    bInheritHandle: 0,
+                },
+                _descriptor: descriptor,
+                _acl: acl,
+            })
+        }
+    }
+
+    /// Wait for a client on a new instance of the pipe. The `first` instance fails if another
+    /// process created the pipe already, so none can listen in the daemon's place.
+    fn accept(name: &[u16], security: &OwnerOnly, first: bool) -> io::Result<File> {
+        let open_mode = match first {
+            true => PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+            false => PIPE_ACCESS_DUPLEX,
+        };
+        // SAFETY: `name` is NUL-terminated, `security` outlives the call and the handle is
+        // checked before use.
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                &security.attributes,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle is a valid pipe owned by nothing else, so the file closes it.
+        let pipe = unsafe { File::from_raw_handle(handle as _) };
+        // SAFETY: the handle is valid for as long as `pipe` lives.
+        if unsafe { ConnectNamedPipe(handle, ptr::null_mut()) } == 0 {
+            let err = io::Error::last_os_error();
+            // The client connected between creating the pipe and waiting for it.
+            if err.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                return Err(err);
+            }
+        }
+        Ok(pipe)
+    }
+
+    /// Answer completions over a named pipe, keeping indexes open and their answers in memory.
+    pub fn run(path: &Path) -> Result<()> {
+        if open(path).is_ok() {
+            bail!("a daemon already listens on {}", path.display());
+        }
+        let name: Vec<u16> = OsStr::new(path)
+            .encode_wide()
+            .chain(iter::once(0))
+            .collect();
+        let security = OwnerOnly::new().context("failed to secure the pipe")?;
+        let mut indexes = Indexes::new();
+        let mut first = true;
+        loop {
+            let pipe = accept(&name, &security, first)
+                .with_context(|| format!("failed to listen on {}", path.display()))?;
+            first = false;
+            let input = match pipe.try_clone() {
+                Ok(input) => input,
+                Err(_) => continue,
+            };
+            // Pipes opened as files have no timeouts, so read the requests on another thread,
+            // which is left blocked if the client keeps silent until it hangs up. A client hanging
+            // up early, or keeping silent, only ends its connection.
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for line in BufReader::new(input).lines() {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+            let lines = iter::from_fn(|| receiver.recv_timeout(REQUEST_TIMEOUT).ok());
+            let _ = connection(lines, pipe, &mut indexes);
+        }
+    }
+
+    /// Complete the command line of this process with the daemon, if one listens on `path` and
+    /// answers in time.
+    pub fn query(path: &Path, deadline: Option<&Deadline>) -> Option<Vec<Candidate>> {
+        let timeout = answer_timeout(deadline)?;
+        let pipe = open(path).ok()?;
+        let input = pipe.try_clone().ok()?;
+        // Pipes opened as files have no timeouts, so wait for the answer on another thread, which
+        // is left blocked if the daemon hangs.
+        let deadline = deadline.cloned();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(ask(input, pipe, deadline.as_ref())));
+        receiver.recv_timeout(timeout).ok()?
+    }
+}
+
+pub use transport::{query, run};
@@ -0,0 +1,258 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use cargo_edit_completion_lib::backend::{self, IndexBackend};
+use cargo_edit_completion_lib::cache::cache_dir;
+use cargo_edit_completion_lib::config::{
+    self, CargoConfig, CRATES_IO, CRATES_IO_INDEX, CRATES_IO_SPARSE,
+};
+use cargo_edit_completion_lib::crates::index_root;
+use cargo_edit_completion_lib::{dump, error, persist};
+
+use crate::install::{self, Shell};
+use crate::stats::location;
+use crate::{index_key, open_registry_index, Opts};
+
+/// Imported dumps older than this are worth refreshing.
+const DUMP_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// The outcome of a check, and how to fix it if it failed.
+struct Finding {
+    ok: bool,
+    what: String,
+    fix: Option<String>,
+}
+
+fn ok(what: String) -> Finding {
+    Finding {
+        ok: true,
+        what,
+        fix: None,
+    }
+}
+
+fn problem(what: String, fix: impl Into<String>) -> Finding {
+    Finding {
+        ok: false,
+        what,
+        fix: Some(fix.into()),
+    }
+}
+
+fn check_crates(name: &str, path: &Path, index: error::Result<Box<dyn IndexBackend>>) -> Finding {
+    match index.and_then(|index| index.crates_with_prefix("")) {
+        Ok(crates) if crates.is_empty() => problem(
+            format!("the index of {} at {} is empty", name, path.display()),
+            "let cargo fetch it, e.g. with `cargo update` in a project, or pass --fetch",
+        ),
+        Ok(crates) => ok(format!(
+            "the index of {} at {} holds {} crates",
+            name,
+            path.display(),
+            crates.len()
+        )),
+        Err(err) => problem(
+            format!(
+                "the index of {} at {} is unreadable: {}",
+                name,
+                path.display(),
+                err
+            ),
+            "check its permissions, or remove it to let cargo fetch it again",
+        ),
+    }
+}
+
+fn check_registry(opts: &Opts, config: &CargoConfig) -> Finding {
+    if let Some(path) = &opts.index_path {
+        if !path.is_dir() {
+            return problem(
+                format!("--index-path {} is no directory", path.display()),
+                "pass a directory under ~/.cargo/registry/index",
+            );
+        }
+        return check_crates(
+            "--index-path",
+            path,
+            Ok(backend::detect(path.clone(), false)),
+        );
+    }
+    let name = opts
+        .registry
+        .as_deref()
+        .unwrap_or_else(|| config.default_registry());
+    let (kind, path) = match location(config, name) {
+        Ok(location) => location,
+        Err(err) => {
+            return problem(
+                format!("registry {} can't be located: {}", name, err),
+                "declare its index under `[registries]` in .cargo/config.toml",
+            )
+        }
+    };
+    match path {
+        Some(path) => check_crates(name, &path, backend::registry(config, name, false)),
+        None if name == CRATES_IO => {
+            let (other, other_kind) = match kind {
+                "sparse" => (CRATES_IO_INDEX, "git"),
+                _ => (CRATES_IO_SPARSE, "sparse"),
+            };
+            let fetched_other = index_root()
+                .ok()
+                .and_then(|root| config::index_dir(&root, other))
+                .is_some();
+            if fetched_other {
+                problem(
+                    format!(
+                        "cargo uses the {} index of crates.io, but only its {} index was fetched",
+                        kind, other_kind
+                    ),
+                    format!(
+                        "set `registries.crates-io.protocol = \"{}\"` in ~/.cargo/config.toml, or \
+                         let cargo fetch the {} index, e.g. with `cargo update` in a project",
+                        other_kind, kind
+                    ),
+                )
+            } else {
+                problem(
+                    "the index of crates.io has not been fetched yet".to_string(),
+                    "let cargo fetch it, e.g. with `cargo update` in a project, or pass --fetch",
+                )
+            }
+        }
+        None => problem(
+            format!("the index of {} has not been fetched yet", name),
+            "let cargo fetch it, e.g. with `cargo update` in a project using the registry",
+        ),
+    }
+}
+
+fn check_cache_dir() -> Finding {
+    let dir = match cache_dir() {
+        Some(dir) => dir,
+        None => {
+            return problem(
+                "no cache directory".to_string(),
+                "set HOME or XDG_CACHE_HOME",
+            )
+        }
+    };
+    let probe = dir.join(".doctor");
+    let writable = fs::create_dir_all(&dir).and_then(|_| fs::write(&probe, ""));
+    let _ = fs::remove_file(&probe);
+    match writable {
+        Ok(_) => ok(format!("the cache directory {} is writable", dir.display())),
+        Err(err) => problem(
+            format!(
+                "the cache directory {} is unwritable: {}",
+                dir.display(),
+                err
+            ),
+            "fix its permissions, or point XDG_CACHE_HOME elsewhere",
+        ),
+    }
+}
+
+fn check_caches(opts: &Opts, config: &CargoConfig) -> Vec<Finding> {
+    let mut findings = vec![];
+    if let Ok(index) = open_registry_index(opts, config) {
+        match persist::stale(&*index, &index_key(opts, config)) {
+            Some(true) => findings.push(problem(
+                "the caches of `warm` are older than the index".to_string(),
+                "they are rebuilt by the next completion; run `cargo_edit_completion warm` to do \
+                 it now",
+            )),
+            Some(false) => findings.push(ok("the caches of `warm` are up to date".to_string())),
+            None => {}
+        }
+    }
+    let modified = dump::default_path()
+        .and_then(|path| fs::metadata(path).ok())
+        .and_then(|meta| meta.modified().ok());
+    if let Some(modified) = modified {
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        findings.push(if age > DUMP_MAX_AGE {
+            problem(
+                format!("the imported dump is {} days old", age.as_secs() / 86400),
+                "refresh it with `cargo_edit_completion import-dump`",
+            )
+        } else {
+            ok("the imported dump is recent".to_string())
+        });
+    }
+    findings
+}
+
+fn check_shell() -> Vec<Finding> {
+    let mut findings = vec![];
+    let on_path = env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            dir.join(env!("CARGO_BIN_NAME")).is_file()
+                || dir.join(concat!(env!("CARGO_BIN_NAME"), ".exe")).is_file()
+        })
+    });
+    findings.push(if on_path {
+        ok(format!("{} is on PATH", env!("CARGO_BIN_NAME")))
+    } else {
+        problem(
+            format!(
+                "{} is not on PATH, so the completion scripts can't run it",
+                env!("CARGO_BIN_NAME")
+            ),
+            "add the directory it is installed to, e.g. ~/.cargo/bin, to PATH",
+        )
+    });
+    let shell = match install::current_shell() {
+        Some(shell) => shell,
+        None => return findings,
+    };
+    let name = match shell {
+        Shell::Zsh => "zsh",
+        Shell::Bash => "bash",
+        Shell::Fish => "fish",
+        Shell::Nushell => "nushell",
+    };
+    let fix = format!(
+        "run `{} install-completions {}`",
+        env!("CARGO_BIN_NAME"),
+        name
+    );
+    findings.push(match install::installed(shell) {
+        Ok(Some(true)) => ok(format!("the {} completion scripts are installed", name)),
+        Ok(Some(false)) => problem(
+            format!("the installed {} completion scripts are outdated", name),
+            fix,
+        ),
+        Ok(None) => problem(format!("no {} completion scripts are installed", name), fix),
+        Err(err) => problem(
+            format!("can't find the {} completion scripts: {}", name, err),
+            fix,
+        ),
+    });
+    findings
+}
+
+/// Check the setup for what keeps completions from working, printing how to fix each problem.
+pub fn doctor(opts: &Opts, config: &CargoConfig) -> Result<()> {
+    let findings = [check_registry(opts, config), check_cache_dir()]
+        .into_iter()
+        .chain(check_caches(opts, config))
+        .chain(check_shell());
+    for finding in findings {
+        println!(
+            "{:<5} {}",
+            if finding.ok { "ok" } else { "warn" },
+            finding.what
+        );
+        if let Some(fix) = finding.fix {
+            println!("      fix: {}", fix);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::ArgEnum;
+
+#[derive(ArgEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Shell {
+    Zsh,
+    Bash,
+    Fish,
+    Nushell,
+}
+
+/// Completion scripts of a shell, as file names and contents.
+fn scripts(shell: Shell) -> &'static [(&'static str, &'static str)] {
+    match shell {
+        Shell::Zsh => &[
+            ("_cargo-add", include_str!("../completions/_cargo-add")),
+            ("_cargo-rm", include_str!("../completions/_cargo-rm")),
+            (
+                "_cargo-upgrade",
+                include_str!("../completions/_cargo-upgrade"),
+            ),
+        ],
+        Shell::Bash => &[(
+            "cargo-edit.bash",
+            include_str!("../completions/cargo-edit.bash"),
+        )],
+        Shell::Fish => &[(
+            "cargo-edit.fish",
+            include_str!("../completions/cargo-edit.fish"),
+        )],
+        Shell::Nushell => &[(
+            "cargo-edit.nu",
+            include_str!("../completions/cargo-edit.nu"),
+        )],
+    }
+}
+
+fn home_dir() -> Result<PathBuf> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("HOME is not set"))
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf> {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => Ok(home_dir()?.join(fallback)),
+    }
+}
+
+/// Directory the scripts of a shell are installed to, and how to enable them if the shell
+/// doesn't pick them up by itself.
+fn target(shell: Shell) -> Result<(PathBuf, Option<&'static str>)> {
+    Ok(match shell {
+        Shell::Zsh => (
+            home_dir()?.join(".zfunc"),
+            Some("add `fpath=(~/.zfunc $fpath)` before `compinit` in your .zshrc"),
+        ),
+        Shell::Bash => (
+            xdg_dir("XDG_DATA_HOME", ".local/share")?.join("cargo-edit-completion"),
+            Some("source cargo-edit.bash from this directory in your .bashrc"),
+        ),
+        Shell::Fish => (xdg_dir("XDG_CONFIG_HOME", ".config")?.join("fish/conf.d"), None),
+        Shell::Nushell => (
+            xdg_dir("XDG_CONFIG_HOME", ".config")?.join("nushell"),
+            Some("`use cargo-edit.nu *` in your config.nu and call `cargo-edit-complete` from your external completer"),
+        ),
+    })
+}
+
+/// The shell of the user, as told by `$SHELL`.
+pub fn current_shell() -> Option<Shell> {
+    let shell = PathBuf::from(env::var_os("SHELL")?);
+    match shell.file_stem()?.to_str()? {
+        "zsh" => Some(Shell::Zsh),
+        "bash" => Some(Shell::Bash),
+        "fish" => Some(Shell::Fish),
+        "nu" => Some(Shell::Nushell),
+        _ => None,
+    }
+}
+
+/// Whether the completion scripts of `shell` are installed, and if so whether they are the ones
+/// of this version.
+pub fn installed(shell: Shell) -> Result<Option<bool>> {
+    let (dir, _) = target(shell)?;
+    let mut current = true;
+    for (name, script) in scripts(shell) {
+        match fs::read_to_string(dir.join(name)) {
+            Ok(installed) => current &= installed == *script,
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Write the completion scripts of `shell` to its completion directory, or print them.
+pub fn install(shell: Shell, print: bool) -> Result<()> {
+    if print {
+        for (_, script) in scripts(shell) {
+            print!("{}", script);
+        }
+        return Ok(());
+    }
+    let (dir, hint) = target(shell)?;
+    fs::create_dir_all(&dir)?;
+    for (name, script) in scripts(shell) {
+        let path = dir.join(name);
+        fs::write(&path, script)?;
+        eprintln!("installed {}", path.display());
+    }
+    if let Some(hint) = hint {
+        eprintln!("to enable the completions, {}", hint);
+    }
+    Ok(())
+}
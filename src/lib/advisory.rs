@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+
+use crate::crates::is_crate_name;
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMeta,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMeta {
+    id: String,
+    #[serde(default)]
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Default, Deserialize)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+/// A security advisory against a crate.
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    pub id: String,
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    /// Whether a version is affected, i.e. neither patched nor unaffected.
+    pub fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|req| req.matches(version))
+    }
+}
+
+/// A checkout of the RustSec advisory database, as kept up to date by `cargo audit`.
+#[derive(Debug, Clone)]
+pub struct AdvisoryDb {
+    path: PathBuf,
+    /// Advisories already read, by crate.
+    cache: Arc<Mutex<HashMap<String, Arc<Vec<Advisory>>>>>,
+}
+
+/// Where `cargo audit` fetches the advisory database to.
+pub fn default_db() -> Option<PathBuf> {
+    Some(crate::config::cargo_home().ok()?.join("advisory-db"))
+}
+
+/// The TOML part of an advisory: the front matter of markdown advisories, or the whole file of
+/// old-style ones.
+fn front_matter(content: &str) -> Option<&str> {
+    match content.trim_start().strip_prefix("```toml") {
+        Some(rest) => Some(&rest[..rest.find("```")?]),
+        None => Some(content),
+    }
+}
+
+fn parse_advisory(path: &Path) -> Option<Advisory> {
+    let content = fs::read_to_string(path).ok()?;
+    let file: AdvisoryFile = toml::from_str(front_matter(&content)?).ok()?;
+    if file.advisory.withdrawn.is_some() {
+        return None;
+    }
+    let parse = |reqs: Vec<String>| -> Vec<VersionReq> {
+        reqs.iter()
+            .filter_map(|req| VersionReq::parse(req).ok())
+            .collect()
+    };
+    Some(Advisory {
+        id: file.advisory.id,
+        patched: parse(file.versions.patched),
+        unaffected: parse(file.versions.unaffected),
+    })
+}
+
+impl AdvisoryDb {
+    pub fn open(path: PathBuf) -> Self {
+        Self {
+            path,
+            cache: Default::default(),
+        }
+    }
+
+    /// Advisories in effect against a crate.
+    pub fn advisories(&self, crate_name: &str) -> Arc<Vec<Advisory>> {
+        let mut cache = self.cache.lock().unwrap();
+        cache
+            .entry(crate_name.to_string())
+            .or_insert_with(|| {
+                // A typed name is joined to the database directory, so it must not lead out of it.
+                if !is_crate_name(crate_name) {
+                    return Arc::default();
+                }
+                let dir = self.path.join("crates").join(crate_name);
+                let advisories = dir
+                    .read_dir()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| parse_advisory(&entry.path()))
+                    .collect();
+                Arc::new(advisories)
+            })
+            .clone()
+    }
+
+    /// Ids of the advisories affecting a crate version.
+    pub fn affecting(&self, crate_name: &str, version: &Version) -> Vec<String> {
+        self.advisories(crate_name)
+            .iter()
+            .filter(|advisory| advisory.affects(version))
+            .map(|advisory| advisory.id.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::backend::IndexBackend;
+use crate::crates::{regexify, Crate};
+use crate::error::{Error, Result};
+use crate::net::{self, Service};
+
+const API_URL: &str = "https://crates.io/api/v1/crates";
+/// Bytes escaped in path segments and query values, all but those crate names are made of.
+const ESCAPED: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_');
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    crates: Vec<SearchEntry>,
+}
+
+#[derive(Deserialize)]
+struct SearchEntry {
+    name: String,
+    #[serde(default)]
+    downloads: u64,
+}
+
+#[derive(Deserialize)]
+struct CrateResponse {
+    versions: Vec<VersionEntry>,
+}
+
+#[derive(Deserialize)]
+struct VersionEntry {
+    #[serde(rename = "crate")]
+    name: String,
+    num: String,
+    #[serde(default)]
+    features: HashMap<String, Vec<String>>,
+    yanked: bool,
+    #[serde(default)]
+    rust_version: Option<String>,
+    #[serde(default)]
+    license: Option<String>,
+}
+
+/// The crates.io web API, covering crates that were never fetched into the local index.
+pub struct ApiIndex;
+
+impl IndexBackend for ApiIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        if prefix.is_empty() {
+            return Ok(vec![]);
+        }
+        let matcher = regexify(prefix);
+        Ok(search(prefix)?
+            .into_iter()
+            .filter(|name| matcher.is_match(name))
+            .collect())
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        versions(name)
+    }
+}
+
+fn get<T: DeserializeOwned>(url: &str) -> Result<Option<T>> {
+    match net::get(Service::Api, url, &[404])? {
+        Some(body) => Ok(Some(
+            serde_json::from_str(&body).map_err(Error::ApiResponse)?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// `part` escaped to be used as a path segment or query value, whatever was typed.
+pub(crate) fn encode(part: &str) -> String {
+    utf8_percent_encode(part, ESCAPED).to_string()
+}
+
+/// Names of crates on crates.io matching the search query.
+pub fn search(query: &str) -> Result<Vec<String>> {
+    let url = format!("{}?q={}&per_page=100", API_URL, encode(query));
+    Ok(match get::<SearchResponse>(&url)? {
+        Some(resp) => resp.crates.into_iter().map(|entry| entry.name).collect(),
+        None => vec![],
+    })
+}
+
+/// Download counts of the most downloaded crates on crates.io matching the search query.
+pub fn downloads(query: &str) -> Result<Vec<(String, u64)>> {
+    let url = format!(
+        "{}?q={}&sort=downloads&per_page=100",
+        API_URL,
+        encode(query)
+    );
+    Ok(match get::<SearchResponse>(&url)? {
+        Some(resp) => resp
+            .crates
+            .into_iter()
+            .map(|entry| (entry.name, entry.downloads))
+            .collect(),
+        None => vec![],
+    })
+}
+
+/// All published versions of a crate, oldest first like in the index.
+pub fn versions(name: &str) -> Result<Option<Vec<Crate>>> {
+    let url = format!("{}/{}", API_URL, encode(name));
+    Ok(match get::<CrateResponse>(&url)? {
+        Some(resp) => Some(
+            resp.versions
+                .into_iter()
+                .rev()
+                .map(|version| Crate {
+                    name: version.name,
+                    version: version.num,
+                    // The API lists dependencies under a separate endpoint per version.
+                    deps: Default::default(),
+                    features: version.features.into(),
+                    yanked: version.yanked,
+                    rust_version: version.rust_version,
+                    edition: None,
+                    license: version.license,
+                })
+                .collect(),
+        ),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_typed_text() {
+        assert_eq!(encode("serde_json-core"), "serde_json-core");
+        assert_eq!(encode("a&per_page=1#"), "a%26per_page%3D1%23");
+        assert_eq!(encode("../x y"), "%2E%2E%2Fx%20y");
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use itertools::Itertools;
+
+use crate::api::ApiIndex;
+use crate::config::{self, CargoConfig, Source};
+use crate::crates::{default_index_dir, index_root, regexify, Crate, CratesIndex};
+use crate::deadline::Deadline;
+use crate::error::{Error, Result};
+use crate::git::GitIndex;
+use crate::local::VendorIndex;
+use crate::sparse::{self, SparseIndex};
+
+/// A source of crate names and versions to complete from.
+pub trait IndexBackend {
+    /// Names of crates starting with `prefix`, treating `-` and `_` alike.
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>>;
+    /// Like [`crates_with_prefix`](Self::crates_with_prefix), but returning the names found so
+    /// far once the deadline passes, for backends able to stop midway.
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        _deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        self.crates_with_prefix(prefix)
+    }
+    /// All versions of a crate, oldest first, or `None` if the crate is unknown.
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>>;
+    /// Something changing whenever the contents of the index do, e.g. the git commit it is at,
+    /// for caches to tell they went stale. `None` if the index can't tell.
+    fn state(&self) -> Option<String> {
+        None
+    }
+    /// Label of the registry a crate is from, for indexes merging several; `None` for others.
+    fn registry(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+/// State of several indexes together.
+fn joint_state<'a>(backends: impl Iterator<Item = &'a dyn IndexBackend>) -> Option<String> {
+    Some(
+        backends
+            .map(|backend| backend.state().unwrap_or_default())
+            .join(","),
+    )
+}
+
+impl<T: IndexBackend + ?Sized> IndexBackend for Box<T> {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        (**self).crates_with_prefix(prefix)
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        (**self).crates_with_prefix_until(prefix, deadline)
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        (**self).versions(name)
+    }
+    fn state(&self) -> Option<String> {
+        (**self).state()
+    }
+    fn registry(&self, name: &str) -> Option<String> {
+        (**self).registry(name)
+    }
+}
+
+/// Answers from the first backend that knows anything, e.g. the local index before the API.
+pub struct FallbackIndex {
+    backends: Vec<Box<dyn IndexBackend>>,
+}
+
+impl FallbackIndex {
+    pub fn new(backends: Vec<Box<dyn IndexBackend>>) -> Self {
+        Self { backends }
+    }
+}
+
+impl IndexBackend for FallbackIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        self.crates_with_prefix_until(prefix, None)
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        for backend in &self.backends {
+            let crates = backend.crates_with_prefix_until(prefix, deadline)?;
+            if !crates.is_empty() {
+                return Ok(crates);
+            }
+        }
+        Ok(vec![])
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        for backend in &self.backends {
+            if let Some(versions) = backend.versions(name)? {
+                return Ok(Some(versions));
+            }
+        }
+        Ok(None)
+    }
+    fn state(&self) -> Option<String> {
+        joint_state(self.backends.iter().map(|backend| &**backend))
+    }
+    fn registry(&self, name: &str) -> Option<String> {
+        self.backends
+            .iter()
+            .find(|backend| matches!(backend.versions(name), Ok(Some(_))))?
+            .registry(name)
+    }
+}
+
+/// Merges the indexes of several registries, e.g. every directory under `registry/index`.
+pub struct MultiIndex {
+    backends: Vec<(String, Box<dyn IndexBackend>)>,
+}
+
+impl MultiIndex {
+    /// Build from backends labelled with the registry they belong to.
+    pub fn new(backends: Vec<(String, Box<dyn IndexBackend>)>) -> Self {
+        Self { backends }
+    }
+    /// Open every registry index cargo has fetched.
+    pub fn discover(fetch: bool) -> Result<Self> {
+        let mut backends = vec![];
+        for entry in index_root()?.read_dir()? {
+            let path = entry?.path();
+            if path.is_dir() {
+                let label = registry_label(&path);
+                backends.push((label, detect(path, fetch)));
+            }
+        }
+        Ok(Self::new(backends))
+    }
+}
+
+impl IndexBackend for MultiIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        self.crates_with_prefix_until(prefix, None)
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        let mut seen = HashSet::new();
+        let mut crates = vec![];
+        for (_, backend) in &self.backends {
+            crates.extend(
+                backend
+                    .crates_with_prefix_until(prefix, deadline)?
+                    .into_iter()
+                    .filter(|name| seen.insert(name.clone())),
+            );
+        }
+        Ok(crates)
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        for (_, backend) in &self.backends {
+            if let Some(versions) = backend.versions(name)? {
+                return Ok(Some(versions));
+            }
+        }
+        Ok(None)
+    }
+    fn state(&self) -> Option<String> {
+        joint_state(self.backends.iter().map(|(_, backend)| &**backend))
+    }
+    fn registry(&self, name: &str) -> Option<String> {
+        self.backends
+            .iter()
+            .find(|(_, backend)| matches!(backend.versions(name), Ok(Some(_))))
+            .map(|(label, _)| label.clone())
+    }
+}
+
+/// Human readable name of a registry index directory, i.e. its host without cargo's hash suffix.
+pub fn registry_label(path: &Path) -> String {
+    let dir_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match dir_name.rsplit_once('-') {
+        Some((host, _hash)) => host.to_string(),
+        None => dir_name,
+    }
+}
+
+/// Remembers what another backend answered, for processes answering many completions.
+pub struct CachedIndex {
+    inner: Box<dyn IndexBackend>,
+    names: Mutex<HashMap<String, Vec<String>>>,
+    versions: Mutex<HashMap<String, Option<Vec<Crate>>>>,
+}
+
+impl CachedIndex {
+    pub fn new(inner: Box<dyn IndexBackend>) -> Self {
+        Self {
+            inner,
+            names: Mutex::default(),
+            versions: Mutex::default(),
+        }
+    }
+}
+
+impl IndexBackend for CachedIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        if let Some(names) = self.names.lock().unwrap().get(prefix) {
+            return Ok(names.clone());
+        }
+        let names = self.inner.crates_with_prefix(prefix)?;
+        self.names
+            .lock()
+            .unwrap()
+            .insert(prefix.to_string(), names.clone());
+        Ok(names)
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        if let Some(names) = self.names.lock().unwrap().get(prefix) {
+            return Ok(names.clone());
+        }
+        let names = self.inner.crates_with_prefix_until(prefix, deadline)?;
+        // Names found before the deadline are only some of them.
+        if !deadline.is_some_and(Deadline::cut_short) {
+            self.names
+                .lock()
+                .unwrap()
+                .insert(prefix.to_string(), names.clone());
+        }
+        Ok(names)
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        if let Some(versions) = self.versions.lock().unwrap().get(name) {
+            return Ok(versions.clone());
+        }
+        let versions = self.inner.versions(name)?;
+        self.versions
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), versions.clone());
+        Ok(versions)
+    }
+    fn state(&self) -> Option<String> {
+        self.inner.state()
+    }
+    fn registry(&self, name: &str) -> Option<String> {
+        self.inner.registry(name)
+    }
+}
+
+/// A fixed set of crates held in memory, useful as a test fixture.
+#[derive(Debug, Default)]
+pub struct MemoryIndex {
+    crates: BTreeMap<String, Vec<Crate>>,
+}
+
+impl MemoryIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a version; versions of a crate should be inserted oldest first.
+    pub fn insert(&mut self, version: Crate) {
+        self.crates
+            .entry(version.name.clone())
+            .or_default()
+            .push(version);
+    }
+}
+
+impl IndexBackend for MemoryIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let matcher = regexify(prefix);
+        Ok(self
+            .crates
+            .keys()
+            .filter(|name| matcher.is_match(name))
+            .cloned()
+            .collect())
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        Ok(self.crates.get(name).cloned())
+    }
+}
+
+/// Open a registry index directory, telling git checkouts, bare git indexes and sparse caches
+/// apart.
+pub fn detect(path: PathBuf, fetch: bool) -> Box<dyn IndexBackend> {
+    if path.join(".git").exists() {
+        if path.join("config.json").exists() {
+            return Box::new(CratesIndex::new(path));
+        }
+        // Cargo no longer checks out git indexes; fall back to its cache if the repo is unusable.
+        match GitIndex::open(&path) {
+            Ok(git) => Box::new(git),
+            Err(_) => Box::new(CratesIndex::new(path.join(".cache"))),
+        }
+    } else if !path.join(".cache").exists() && holds_buckets(&path) {
+        // A checked out or extracted index, or the one of a local registry.
+        Box::new(CratesIndex::new(path))
+    } else {
+        let remote = if fetch {
+            sparse::index_url(&path)
+        } else {
+            None
+        };
+        Box::new(SparseIndex::new(&path, remote))
+    }
+}
+
+/// Whether `path` holds index entries itself: a `config.json`, or buckets like `1/` or `ab/cd/`.
+fn holds_buckets(path: &Path) -> bool {
+    let is_bucket = |path: &Path| {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        path.is_dir() && name.len() == 2 && !name.contains('.')
+    };
+    path.join("config.json").is_file()
+        || ["1", "2", "3"].iter().any(|name| path.join(name).is_dir())
+        || path.read_dir().is_ok_and(|entries| {
+            entries.filter_map(|entry| entry.ok()).any(|entry| {
+                is_bucket(&entry.path())
+                    && entry.path().read_dir().is_ok_and(|mut entries| {
+                        entries.any(|entry| entry.is_ok_and(|entry| is_bucket(&entry.path())))
+                    })
+            })
+        })
+}
+
+/// Index of a registry declared in cargo's configuration, honoring source replacement.
+pub fn registry(config: &CargoConfig, name: &str, fetch: bool) -> Result<Box<dyn IndexBackend>> {
+    match config.resolve_source(name)? {
+        Source::Registry(url) => from_url(&url, fetch),
+        Source::LocalRegistry(path) => Ok(Box::new(CratesIndex::local_registry(&path))),
+        Source::Directory(path) => Ok(Box::new(VendorIndex::new(path))),
+    }
+}
+
+/// Index of the registry at `url`, located under cargo's registry directory.
+pub fn from_url(url: &str, fetch: bool) -> Result<Box<dyn IndexBackend>> {
+    let path = config::index_dir(&index_root()?, url);
+    match (path, url.strip_prefix("sparse+")) {
+        (Some(path), Some(sparse_url)) => Ok(Box::new(SparseIndex::new(
+            &path,
+            fetch.then(|| format!("{}/", sparse_url.trim_end_matches('/'))),
+        ))),
+        (None, Some(sparse_url)) if fetch => Ok(Box::new(SparseIndex::new(
+            Path::new(""),
+            Some(format!("{}/", sparse_url.trim_end_matches('/'))),
+        ))),
+        (Some(path), None) => Ok(detect(path, false)),
+        (None, _) => Err(Error::IndexNotFetched(url.to_string())),
+    }
+}
+
+/// Index of the default registry, or any fetched index if it can't be located.
+pub fn default_registry(config: &CargoConfig, fetch: bool) -> Result<Box<dyn IndexBackend>> {
+    registry(config, config.default_registry(), fetch)
+        .or_else(|_| Ok(detect(default_index_dir()?, fetch)))
+}
+
+/// Fall back to the crates.io API when `index` doesn't know a crate.
+pub fn with_online_fallback(index: Box<dyn IndexBackend>) -> Box<dyn IndexBackend> {
+    Box::new(FallbackIndex::new(vec![index, Box::new(ApiIndex)]))
+}
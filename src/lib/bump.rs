@@ -0,0 +1,85 @@
+use semver::{BuildMetadata, Prerelease, Version};
+
+/// Levels accepted by `cargo set-version --bump`, most significant first.
+pub const BUMP_LEVELS: &[&str] = &["major", "minor", "patch", "release", "rc", "beta", "alpha"];
+
+/// Prerelease levels, lowest first.
+const PRE_LEVELS: &[&str] = &["alpha", "beta", "rc"];
+
+/// The version `cargo set-version --bump <level>` moves `current` to, if the bump is valid.
+pub fn bump(current: &Version, level: &str) -> Option<Version> {
+    let mut next = Version::new(current.major, current.minor, current.patch);
+    match level {
+        "major" => {
+            next.major += 1;
+            next.minor = 0;
+            next.patch = 0;
+        }
+        "minor" => {
+            next.minor += 1;
+            next.patch = 0;
+        }
+        "patch" => next.patch += 1,
+        // Ending a prerelease keeps the version it led up to.
+        "release" if !current.pre.is_empty() => {}
+        "release" => return None,
+        level => {
+            let rank = PRE_LEVELS.iter().position(|pre| *pre == level)?;
+            let (current_level, number) = match current.pre.as_str().split_once('.') {
+                Some((pre, number)) => (pre, number.parse::<u64>().ok()?),
+                None => (current.pre.as_str(), 0),
+            };
+            let number = match PRE_LEVELS.iter().position(|pre| *pre == current_level) {
+                Some(current_rank) if current_rank == rank => number + 1,
+                Some(current_rank) if current_rank < rank => 1,
+                // Going back to a lower prerelease level is refused like cargo-edit does.
+                Some(_) => return None,
+                None if current.pre.is_empty() => {
+                    next.patch += 1;
+                    1
+                }
+                None => return None,
+            };
+            next.pre = Prerelease::new(&format!("{}.{}", level, number)).ok()?;
+        }
+    }
+    next.build = BuildMetadata::EMPTY;
+    Some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bumped(current: &str, level: &str) -> Option<String> {
+        bump(&Version::parse(current).unwrap(), level).map(|next| next.to_string())
+    }
+
+    #[test]
+    fn release_levels() {
+        assert_eq!(bumped("1.2.3", "major").as_deref(), Some("2.0.0"));
+        assert_eq!(bumped("1.2.3", "minor").as_deref(), Some("1.3.0"));
+        assert_eq!(bumped("1.2.3", "patch").as_deref(), Some("1.2.4"));
+        assert_eq!(bumped("1.2.3+build.5", "patch").as_deref(), Some("1.2.4"));
+        assert_eq!(bumped("1.2.3", "release"), None);
+        assert_eq!(bumped("1.2.3", "huge"), None);
+    }
+
+    #[test]
+    fn prerelease_levels() {
+        assert_eq!(bumped("1.2.3", "alpha").as_deref(), Some("1.2.4-alpha.1"));
+        assert_eq!(
+            bumped("1.2.4-alpha.1", "alpha").as_deref(),
+            Some("1.2.4-alpha.2")
+        );
+        assert_eq!(
+            bumped("1.2.4-alpha", "alpha").as_deref(),
+            Some("1.2.4-alpha.1")
+        );
+        assert_eq!(bumped("1.2.4-alpha.2", "rc").as_deref(), Some("1.2.4-rc.1"));
+        assert_eq!(bumped("1.2.4-rc.1", "release").as_deref(), Some("1.2.4"));
+        // No going back to a lower level, nor from prereleases of unknown levels.
+        assert_eq!(bumped("1.2.4-rc.1", "beta"), None);
+        assert_eq!(bumped("1.2.4-dev.1", "beta"), None);
+    }
+}
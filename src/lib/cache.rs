@@ -0,0 +1,58 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Cache directory configured in place of the default one, as set by [`set_cache_dir`].
+static CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Cache in `dir` from now on instead of the default directory, or in the default one again for
+/// `None`.
+pub fn set_cache_dir(dir: Option<PathBuf>) {
+    *CACHE_DIR.lock().unwrap() = dir;
+}
+
+/// Name for the cache entry of `key`, a hex FNV-1a hash of it as that stays the same across builds
+/// unlike the hasher of the standard library.
+pub(crate) fn hashed(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Directory completion results expensive to compute are cached in.
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = &*CACHE_DIR.lock().unwrap() {
+        return Some(dir.clone());
+    }
+    let base = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join(env!("CARGO_PKG_NAME")))
+}
+
+/// Read a cache entry unless it is older than `ttl`.
+pub fn read(name: &str, ttl: Duration) -> Option<String> {
+    let path = cache_dir()?.join(name);
+    let age = SystemTime::now()
+        .duration_since(fs::metadata(&path).ok()?.modified().ok()?)
+        .unwrap_or_default();
+    if age > ttl {
+        return None;
+    }
+    fs::read_to_string(path).ok()
+}
+
+/// Write a cache entry, ignoring failures as the cache is only an optimization.
+pub fn write(name: &str, content: &str) {
+    if let Some(dir) = cache_dir() {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let _ = fs::write(path, content);
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use serde::de::DeserializeOwned;
+use toml::Value;
+
+use crate::error::{Error, Result};
+
+pub const CRATES_IO: &str = "crates-io";
+pub const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+pub const CRATES_IO_SPARSE: &str = "sparse+https://index.crates.io/";
+
+/// Cargo configuration merged from `.cargo/config.toml` files, most specific first, and
+/// overridden by `CARGO_*` environment variables.
+#[derive(Debug, Default)]
+pub struct CargoConfig {
+    /// Parsed files along with the directory relative paths in them are resolved against.
+    tables: Vec<(PathBuf, Value)>,
+    /// `CARGO_*` environment variables, e.g. `CARGO_REGISTRIES_FOO_INDEX` for
+    /// `registries.foo.index`.
+    env: HashMap<String, Value>,
+    /// Directory relative paths in environment variables are resolved against.
+    cwd: PathBuf,
+}
+
+/// Where cargo actually fetches a registry's crates from after source replacement.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Source {
+    /// A remote registry index, `sparse+`-prefixed for sparse registries.
+    Registry(String),
+    /// A directory created by `cargo local-registry`.
+    LocalRegistry(PathBuf),
+    /// A directory of unpacked crates, e.g. produced by `cargo vendor`.
+    Directory(PathBuf),
+}
+
+impl CargoConfig {
+    /// Load configuration the way cargo does: from the environment, then `cwd` and its ancestors,
+    /// then `CARGO_HOME`.
+    pub fn load(cwd: &Path) -> Result<Self> {
+        Self::load_from(cwd, env::vars())
+    }
+
+    /// Like [`load`](Self::load), with the environment variables `vars` instead of those of this
+    /// process, e.g. the ones of a client.
+    pub fn load_from(cwd: &Path, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut files: Vec<PathBuf> = cwd
+            .ancestors()
+            .filter_map(|dir| config_file(&dir.join(".cargo")))
+            .collect();
+        if let Some(file) = cargo_home().ok().and_then(|home| config_file(&home)) {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        let tables = files
+            .into_iter()
+            .map(|file| {
+                // `<dir>/.cargo/config.toml` resolves paths relative to `<dir>`.
+                let base = file
+                    .parent()
+                    .and_then(Path::parent)
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default();
+                Ok((base, read_toml(&file)?))
+            })
+            .collect::<Result<_>>()?;
+        let env = vars
+            .into_iter()
+            .filter(|(key, _)| key.starts_with("CARGO_"))
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect();
+        Ok(Self {
+            tables,
+            env,
+            cwd: cwd.to_path_buf(),
+        })
+    }
+
+    pub fn load_default() -> Result<Self> {
+        Self::load(&env::current_dir()?)
+    }
+
+    /// Directory the configuration was loaded for, which manifests are looked up from.
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Look up a dotted key, e.g. `["registries", "foo", "index"]`.
+    pub fn get(&self, key: &[&str]) -> Option<&Value> {
+        self.get_with_base(key).map(|(_, value)| value)
+    }
+
+    fn get_with_base(&self, key: &[&str]) -> Option<(&Path, &Value)> {
+        if let Some(value) = self.env.get(&env_key(key)) {
+            return Some((self.cwd.as_path(), value));
+        }
+        self.tables.iter().find_map(|(base, table)| {
+            key.iter()
+                .try_fold(table, |value, part| value.as_table()?.get(*part))
+                .map(|value| (base.as_path(), value))
+        })
+    }
+
+    pub fn get_str(&self, key: &[&str]) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    /// Look up a boolean, which environment variables spell as `true` or `false`.
+    pub fn get_bool(&self, key: &[&str]) -> Option<bool> {
+        match self.get(key)? {
+            Value::Boolean(value) => Some(*value),
+            Value::String(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Look up a list of strings, concatenating the lists of all files like cargo merges arrays.
+    /// The table under a dotted key merged across files: the most specific file's value of each
+    /// key wins, while arrays are concatenated as cargo does.
+    pub fn table(&self, key: &[&str]) -> toml::Table {
+        let mut merged = toml::Table::new();
+        for (_, table) in &self.tables {
+            let table = key
+                .iter()
+                .try_fold(table, |value, part| value.as_table()?.get(*part))
+                .and_then(Value::as_table);
+            for (name, value) in table.into_iter().flatten() {
+                match (merged.get_mut(name), value) {
+                    (Some(Value::Array(merged)), Value::Array(items)) => {
+                        merged.extend(items.iter().cloned())
+                    }
+                    (Some(_), _) => {}
+                    (None, value) => {
+                        merged.insert(name.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        merged
+    }
+
+    pub fn get_list(&self, key: &[&str]) -> Vec<String> {
+        self.tables
+            .iter()
+            .filter_map(|(_, table)| {
+                key.iter()
+                    .try_fold(table, |value, part| value.as_table()?.get(*part))?
+                    .as_array()
+            })
+            .flatten()
+            .filter_map(|item| Some(item.as_str()?.to_string()))
+            .collect()
+    }
+
+    /// Look up a path, resolving it relative to the config file that declared it.
+    pub fn get_path(&self, key: &[&str]) -> Option<PathBuf> {
+        let (base, value) = self.get_with_base(key)?;
+        Some(base.join(value.as_str()?))
+    }
+
+    /// Resolve the source a registry's lookups go to, following `replace-with` like cargo.
+    pub fn resolve_source(&self, registry: &str) -> Result<Source> {
+        let mut name = registry.to_string();
+        for _ in 0..16 {
+            if let Some(replacement) = self.get_str(&["source", &name, "replace-with"]) {
+                name = replacement.to_string();
+                continue;
+            }
+            if let Some(url) = self.get_str(&["source", &name, "registry"]) {
+                return Ok(Source::Registry(url.to_string()));
+            }
+            if let Some(path) = self.get_path(&["source", &name, "local-registry"]) {
+                return Ok(Source::LocalRegistry(path));
+            }
+            if let Some(path) = self.get_path(&["source", &name, "directory"]) {
+                return Ok(Source::Directory(path));
+            }
+            return Ok(Source::Registry(self.registry_index(&name)?));
+        }
+        Err(Error::CyclicReplacement(registry.to_string()))
+    }
+
+    /// Whether cargo must not access the network, by `net.offline` or `CARGO_NET_OFFLINE`.
+    pub fn net_offline(&self) -> bool {
+        self.get_bool(&["net", "offline"]).unwrap_or(false)
+    }
+
+    /// Name of the registry `cargo add` uses when none is given.
+    pub fn default_registry(&self) -> &str {
+        self.get_str(&["registry", "default"]).unwrap_or(CRATES_IO)
+    }
+
+    /// Names of the registries declared in `[registries]` or by `CARGO_REGISTRIES_<NAME>_INDEX`.
+    pub fn registry_names(&self) -> Vec<String> {
+        let declared = self
+            .tables
+            .iter()
+            .filter_map(|(_, table)| table.get("registries")?.as_table())
+            .flat_map(|registries| registries.keys().cloned());
+        let from_env = self.env.keys().filter_map(|key| {
+            let name = key
+                .strip_prefix("CARGO_REGISTRIES_")?
+                .strip_suffix("_INDEX")?;
+            Some(name.to_lowercase())
+        });
+        declared.chain(from_env).sorted().dedup().collect()
+    }
+
+    /// Index url of a named registry, `sparse+`-prefixed for sparse registries.
+    pub fn registry_index(&self, name: &str) -> Result<String> {
+        if name == CRATES_IO {
+            let protocol = self.get_str(&["registries", CRATES_IO, "protocol"]);
+            return Ok(match protocol {
+                Some("git") => CRATES_IO_INDEX,
+                _ => CRATES_IO_SPARSE,
+            }
+            .to_string());
+        }
+        self.get_str(&["registries", name, "index"])
+            .map(str::to_string)
+            .ok_or_else(|| Error::RegistryNotConfigured(name.to_string()))
+    }
+}
+
+/// Cargo's home directory, like [`home::cargo_home`] but forgiving how `CARGO_HOME` tends to be set
+/// on Windows: quoted, with trailing whitespace or with `%VAR%` references left unexpanded.
+pub fn cargo_home() -> io::Result<PathBuf> {
+    let var = env::var("CARGO_HOME").unwrap_or_default();
+    let var = var.trim().trim_matches('"');
+    if var.is_empty() {
+        return home::cargo_home();
+    }
+    let path = PathBuf::from(expand_env(var));
+    Ok(match path.is_absolute() {
+        true => path,
+        false => env::current_dir()?.join(path),
+    })
+}
+
+/// Expand `%VAR%` references the way `cmd` does, leaving unknown ones as they are.
+#[cfg(windows)]
+fn expand_env(value: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('%') {
+        let Some(len) = rest[start + 1..].find('%') else {
+            break;
+        };
+        let name = &rest[start + 1..start + 1 + len];
+        expanded.push_str(&rest[..start]);
+        match env::var(name) {
+            Ok(var) if !name.is_empty() => expanded.push_str(&var),
+            _ => expanded.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(not(windows))]
+fn expand_env(value: &str) -> String {
+    value.to_string()
+}
+
+/// Environment variable overriding a config key, e.g. `CARGO_REGISTRY_DEFAULT` for
+/// `registry.default`.
+fn env_key(key: &[&str]) -> String {
+    let key = key.join("_").to_uppercase().replace(['-', '.'], "_");
+    format!("CARGO_{}", key)
+}
+
+/// Read and parse a TOML file, e.g. a configuration file or a manifest.
+pub(crate) fn read_toml<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = fs::read_to_string(path).map_err(|source| Error::FileRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    toml::from_str(&content).map_err(|source| Error::Toml {
+        path: path.to_path_buf(),
+        source: Box::new(source),
+    })
+}
+
+fn config_file(dir: &Path) -> Option<PathBuf> {
+    ["config.toml", "config"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Host part of an index url, which cargo uses to name the index directory.
+pub fn url_host(url: &str) -> Option<&str> {
+    let url = url.strip_prefix("sparse+").unwrap_or(url);
+    let (_scheme, rest) = url.split_once("://")?;
+    let host = rest.split(&['/', ':'][..]).next()?;
+    Some(host.rsplit('@').next().unwrap_or(host))
+}
+
+/// SipHash of `data` with zero keys and `c` compression and `d` finalization rounds: the 64-bit
+/// hash, or both halves of the 128-bit one if `wide`.
+fn sip_hash(data: &[u8], c: usize, d: usize, wide: bool) -> (u64, u64) {
+    let mut v = [
+        0x736f_6d65_7073_6575_u64,
+        0x646f_7261_6e64_6f6d ^ if wide { 0xee } else { 0 },
+        0x6c79_6765_6e65_7261,
+        0x7465_6462_7974_6573,
+    ];
+    let rounds = |v: &mut [u64; 4], count: usize| {
+        for _ in 0..count {
+            v[0] = v[0].wrapping_add(v[1]);
+            v[1] = v[1].rotate_left(13) ^ v[0];
+            v[0] = v[0].rotate_left(32);
+            v[2] = v[2].wrapping_add(v[3]);
+            v[3] = v[3].rotate_left(16) ^ v[2];
+            v[0] = v[0].wrapping_add(v[3]);
+            v[3] = v[3].rotate_left(21) ^ v[0];
+            v[2] = v[2].wrapping_add(v[1]);
+            v[1] = v[1].rotate_left(17) ^ v[2];
+            v[2] = v[2].rotate_left(32);
+        }
+    };
+    let chunks = data.chunks_exact(8);
+    let mut last = [0; 8];
+    last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    last[7] = data.len() as u8;
+    for chunk in chunks.map(|chunk| chunk.try_into().unwrap()).chain([last]) {
+        let word = u64::from_le_bytes(chunk);
+        v[3] ^= word;
+        rounds(&mut v, c);
+        v[0] ^= word;
+    }
+    v[2] ^= if wide { 0xee } else { 0xff };
+    rounds(&mut v, d);
+    let first = v[0] ^ v[1] ^ v[2] ^ v[3];
+    v[1] ^= 0xdd;
+    rounds(&mut v, d);
+    (first, v[0] ^ v[1] ^ v[2] ^ v[3])
+}
+
+/// Names cargo gives the directory under `registry/index` of the index at `url`: `<host>-<hash>`,
+/// hashing the kind of the source and its url the way cargo before 1.85 did and the way it does
+/// since.
+fn index_dir_names(url: &str) -> Option<[String; 2]> {
+    let host = url_host(url)?;
+    // The discriminant of `SourceKind::SparseRegistry` or `SourceKind::Registry`.
+    let kind: u8 = if url.starts_with("sparse+") { 3 } else { 2 };
+    // Strings are hashed with a trailing 0xff.
+    let hashed_url = url.as_bytes().iter().chain(&[0xff]);
+    // SipHash-2-4, hashing the discriminant as 8 bytes.
+    let old: Vec<u8> = [kind, 0, 0, 0, 0, 0, 0, 0]
+        .iter()
+        .chain(hashed_url.clone())
+        .copied()
+        .collect();
+    let (old, _) = sip_hash(&old, 2, 4, false);
+    // SipHash-1-3 with 128 bits, hashing small discriminants as 1 byte and combining the halves.
+    let new: Vec<u8> = [kind].iter().chain(hashed_url).copied().collect();
+    let (first, second) = sip_hash(&new, 1, 3, true);
+    let new = first.wrapping_mul(3).wrapping_add(second);
+    // Hex of the little-endian bytes of the hash.
+    Some([old, new].map(|hash| format!("{}-{:016x}", host, hash.swap_bytes())))
+}
+
+/// Find the directory under `registry/index` cargo uses for the index at `url`.
+///
+/// Cargo 1.85 changed how the url is hashed, so both names are looked for; when both directories
+/// exist, the most recently used one wins.
+pub fn index_dir(index_root: &Path, url: &str) -> Option<PathBuf> {
+    let names = index_dir_names(url)?;
+    index_root
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| names.iter().any(|name| entry.file_name() == name.as_str()))
+        .max_by_key(|entry| entry.metadata().and_then(|meta| meta.modified()).ok())
+        .map(|entry| entry.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Configuration of a single `/project/.cargo/config.toml` holding `content`.
+    fn config(content: &str) -> CargoConfig {
+        CargoConfig {
+            tables: vec![(PathBuf::from("/project"), toml::from_str(content).unwrap())],
+            ..CargoConfig::default()
+        }
+    }
+
+    #[test]
+    fn index_dirs_named_like_cargo() {
+        assert_eq!(
+            index_dir_names(CRATES_IO_SPARSE).unwrap(),
+            [
+                "index.crates.io-6f17d22bba15001f",
+                "index.crates.io-1949cf8c6b5b557f"
+            ]
+        );
+        assert_eq!(
+            index_dir_names(CRATES_IO_INDEX).unwrap()[0],
+            "github.com-1ecc6299db9ec823"
+        );
+    }
+
+    #[test]
+    fn replacement_chains() {
+        let config = config(
+            r#"
+            [source.crates-io]
+            replace-with = "mirror"
+            [source.mirror]
+            replace-with = "vendored"
+            [source.vendored]
+            directory = "vendor"
+            [source.offline]
+            local-registry = "/registry"
+            [source.remote]
+            registry = "https://example.com/index"
+            [registries.mine]
+            index = "sparse+https://example.com/mine/"
+            "#,
+        );
+        assert_eq!(
+            config.resolve_source(CRATES_IO).unwrap(),
+            Source::Directory(PathBuf::from("/project/vendor"))
+        );
+        assert_eq!(
+            config.resolve_source("offline").unwrap(),
+            Source::LocalRegistry(PathBuf::from("/registry"))
+        );
+        assert_eq!(
+            config.resolve_source("remote").unwrap(),
+            Source::Registry("https://example.com/index".to_string())
+        );
+        assert_eq!(
+            config.resolve_source("mine").unwrap(),
+            Source::Registry("sparse+https://example.com/mine/".to_string())
+        );
+        assert!(matches!(
+            config.resolve_source("unknown"),
+            Err(Error::RegistryNotConfigured(name)) if name == "unknown"
+        ));
+    }
+
+    #[test]
+    fn replacement_cycles() {
+        let cyclic = config(
+            r#"
+            [source.crates-io]
+            replace-with = "mirror"
+            [source.mirror]
+            replace-with = "crates-io"
+            "#,
+        );
+        assert!(matches!(
+            cyclic.resolve_source(CRATES_IO),
+            Err(Error::CyclicReplacement(name)) if name == CRATES_IO
+        ));
+        assert_eq!(
+            config("").resolve_source(CRATES_IO).unwrap(),
+            Source::Registry(CRATES_IO_SPARSE.to_string())
+        );
+    }
+}
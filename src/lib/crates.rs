@@ -1,14 +1,30 @@
 use std::collections::HashMap;
-use anyhow::Result;
+use std::fmt::{self, Debug, Formatter};
 use std::fs;
 use std::io;
+use std::iter;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime};
 
 use either::Either;
 use itertools::Itertools;
+use rayon::prelude::*;
 use regex::Regex;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::{to_raw_value, RawValue};
+use tracing::{debug, trace, warn};
 
+use crate::backend::IndexBackend;
+use crate::config;
+use crate::deadline::Deadline;
+use crate::error::{Error, Result};
+
+/// An index laid out as files in a directory, i.e. a git checkout, cargo's cache of a registry or
+/// the `index` directory of a local registry.
 pub struct CratesIndex {
     path: PathBuf,
 }
@@ -19,64 +35,462 @@ pub struct CrateMeta {
     pub path: PathBuf,
 }
 
+/// How many parsed index files [`CrateMeta::detail`] keeps around.
+const DETAIL_CACHE_SIZE: usize = 64;
+
+/// Index files parsed lately by path and modification time, most recently used first.
+type DetailCache = Vec<((PathBuf, SystemTime), Vec<Crate>)>;
+
+static DETAILS: Mutex<DetailCache> = Mutex::new(Vec::new());
+
+/// How many index files were parsed so far, as told by [`parsed_files`].
+static PARSED: AtomicUsize = AtomicUsize::new(0);
+
+/// How many index files this process parsed so far.
+pub fn parsed_files() -> usize {
+    PARSED.load(Ordering::Relaxed)
+}
+
 impl CrateMeta {
+    /// All versions in the index file, reusing the last parse of the file if it didn't change
+    /// since, as completing versions and then features reads the same crate again.
     pub fn detail(&self) -> Result<Vec<Crate>> {
-        let lines = fs::read_to_string(&self.path)?;
-        Ok(lines
+        let key = (self.path.clone(), fs::metadata(&self.path)?.modified()?);
+        let mut details = DETAILS.lock().unwrap();
+        if let Some(pos) = details.iter().position(|(cached, _)| *cached == key) {
+            let entry = details.remove(pos);
+            let versions = entry.1.clone();
+            details.insert(0, entry);
+            return Ok(versions);
+        }
+        drop(details);
+        let content = fs::read(&self.path)?;
+        let versions = if content.first() == Some(&CACHE_VERSION) {
+            parse_cache(&content)?
+        } else {
+            let lines = std::str::from_utf8(&content)
+                .map_err(|_| Error::IndexFormat("index file is not utf-8".to_string()))?;
+            parse_lines(lines)?
+        };
+        PARSED.fetch_add(1, Ordering::Relaxed);
+        debug!(path = %self.path.display(), versions = versions.len(), "parsed index file");
+        let mut details = DETAILS.lock().unwrap();
+        details.insert(0, (key, versions.clone()));
+        details.truncate(DETAIL_CACHE_SIZE);
+        Ok(versions)
+    }
+}
+
+/// How many malformed index entries were skipped so far, as told by [`skipped_entries`].
+static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// How many malformed index entries this process skipped so far.
+pub fn skipped_entries() -> usize {
+    SKIPPED.load(Ordering::Relaxed)
+}
+
+/// Parse the entries of an index file, skipping malformed ones with a warning so a single
+/// corrupted entry doesn't hide the other versions of a crate.
+///
+/// Fails only if no entry could be parsed, as the file is then unlikely to be an index file.
+fn parse_entries<'a>(entries: impl Iterator<Item = &'a [u8]>) -> Result<Vec<Crate>> {
+    let mut versions = vec![];
+    let mut first_error = None;
+    for (number, entry) in entries.enumerate() {
+        match serde_json::from_slice(entry) {
+            Ok(version) => versions.push(version),
+            Err(err) => {
+                warn!(entry = number + 1, error = %err, "skipping malformed index entry");
+                SKIPPED.fetch_add(1, Ordering::Relaxed);
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    match first_error {
+        Some(err) if versions.is_empty() => Err(err.into()),
+        _ => Ok(versions),
+    }
+}
+
+/// Parse an index file in the JSON lines format served by git and sparse registries.
+pub(crate) fn parse_lines(lines: &str) -> Result<Vec<Crate>> {
+    parse_entries(
+        lines
             .trim()
             .lines()
-            .map(|line| serde_json::from_str(line))
-            .try_collect()?)
+            .filter(|line| !line.trim().is_empty())
+            .map(str::as_bytes),
+    )
+}
+
+/// Version of cargo's index cache format this parser understands.
+const CACHE_VERSION: u8 = 3;
+/// Highest index format version (the `v` field of entries) cargo currently emits.
+const INDEX_V_MAX: u32 = 2;
+
+/// Parse a file in cargo's binary index cache format.
+///
+/// The layout is a cache version byte, a little-endian `u32` index format version and a
+/// NUL-terminated index version (git commit or HTTP etag), followed by NUL-terminated pairs of
+/// version and JSON entry.
+fn parse_cache(content: &[u8]) -> Result<Vec<Crate>> {
+    let (&cache_version, rest) = content
+        .split_first()
+        .ok_or_else(|| Error::IndexFormat("empty cache file".to_string()))?;
+    if cache_version != CACHE_VERSION {
+        return Err(Error::IndexFormat(format!(
+            "unsupported cache version {}",
+            cache_version
+        )));
+    }
+    let (index_v, rest) = match (rest.get(..4), rest.get(4..)) {
+        (Some(index_v), Some(rest)) => (index_v, rest),
+        _ => return Err(Error::IndexFormat("truncated cache file".to_string())),
+    };
+    let index_v = u32::from_le_bytes([index_v[0], index_v[1], index_v[2], index_v[3]]);
+    if index_v > INDEX_V_MAX {
+        return Err(Error::IndexFormat(format!(
+            "unsupported index format version {}",
+            index_v
+        )));
+    }
+
+    let mut fields = rest.split(|&b| b == 0);
+    let _index_version = fields.next();
+    parse_entries(fields.tuples().map(|(_version, entry)| entry))
+}
+
+/// What a [`Lazy`] value can be made of, each part parsed from JSON on its own.
+pub trait Parts:
+    DeserializeOwned + Default + IntoIterator + Extend<<Self as IntoIterator>::Item>
+{
+}
+
+impl<T: DeserializeOwned + Default + IntoIterator + Extend<<T as IntoIterator>::Item>> Parts for T {}
+
+/// A field of an index entry only parsed once it is looked at, as completions need little more
+/// than the version numbers of most versions. Holds the JSON of the parts to merge into the value,
+/// e.g. `features` and `features2`.
+#[derive(Clone)]
+pub struct Lazy<T> {
+    raw: Vec<Box<RawValue>>,
+    value: OnceLock<T>,
+}
+
+impl<T> Lazy<T> {
+    fn from_raw(raw: impl IntoIterator<Item = Box<RawValue>>) -> Self {
+        Self {
+            raw: raw.into_iter().collect(),
+            value: OnceLock::new(),
+        }
+    }
+}
+
+impl<T: Parts> Deref for Lazy<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.get_or_init(|| {
+            let mut value = T::default();
+            for raw in &self.raw {
+                // The entry parsed as JSON, so only a part of some unexpected shape can fail,
+                // which is left out like a missing one.
+                if let Ok(part) = serde_json::from_str::<T>(raw.get()) {
+                    value.extend(part);
+                }
+            }
+            value
+        })
+    }
+}
+
+impl<T> From<T> for Lazy<T> {
+    fn from(value: T) -> Self {
+        Self {
+            raw: vec![],
+            value: OnceLock::from(value),
+        }
+    }
+}
+
+impl<T: Default> Default for Lazy<T> {
+    fn default() -> Self {
+        T::default().into()
+    }
+}
+
+impl<T: Parts + Debug> Debug for Lazy<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
     }
 }
 
-#[derive(Debug, Deserialize)]
+impl<T: Parts + PartialEq> PartialEq for Lazy<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Parts + Eq> Eq for Lazy<T> {}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(from = "IndexEntry", into = "IndexEntry")]
 pub struct Crate {
     pub name: String,
-    #[serde(rename = "vers")]
     pub version: String,
-    pub features: HashMap<String, Vec<String>>,
+    pub deps: Lazy<Vec<Dependency>>,
+    pub features: Lazy<HashMap<String, Vec<String>>>,
     pub yanked: bool,
+    /// Minimum supported Rust version, if declared.
+    pub rust_version: Option<String>,
+    /// Edition of the crate, only known for unpacked crates.
+    pub edition: Option<String>,
+    /// SPDX license expression, unknown for crates read from an index.
+    pub license: Option<String>,
 }
 
-impl Default for CratesIndex {
-    fn default() -> Self {
+/// A line of the index as stored, before merging `features2` into `features`.
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    name: String,
+    vers: String,
+    #[serde(default)]
+    deps: Option<Box<RawValue>>,
+    features: Box<RawValue>,
+    /// Features using `dep:` or `?/` syntax, kept apart so older cargo versions skip them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    features2: Option<Box<RawValue>>,
+    yanked: bool,
+    #[serde(default)]
+    rust_version: Option<String>,
+    /// Not in indexes, but kept when caching versions read from elsewhere.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    edition: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+}
+
+impl From<IndexEntry> for Crate {
+    fn from(entry: IndexEntry) -> Self {
+        Self {
+            name: entry.name,
+            version: entry.vers,
+            deps: Lazy::from_raw(entry.deps),
+            features: Lazy::from_raw(iter::once(entry.features).chain(entry.features2)),
+            yanked: entry.yanked,
+            rust_version: entry.rust_version,
+            edition: entry.edition,
+            license: entry.license,
+        }
+    }
+}
+
+impl From<Crate> for IndexEntry {
+    fn from(version: Crate) -> Self {
         Self {
-            path: home::cargo_home()
-                .unwrap()
-                .join("registry")
-                .join("index")
-                .read_dir()
-                .unwrap()
-                .next()
-                .unwrap()
-                .unwrap()
-                .path(),
+            name: version.name,
+            vers: version.version,
+            deps: to_raw_value(&*version.deps).ok(),
+            features: to_raw_value(&*version.features).unwrap_or_default(),
+            features2: None,
+            yanked: version.yanked,
+            rust_version: version.rust_version,
+            edition: version.edition,
+            license: version.license,
         }
     }
 }
 
+/// A dependency of a crate version as recorded in the index.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Dependency {
+    /// Name the dependency is known as in the dependent, which differs from `package` when renamed.
+    pub name: String,
+    pub req: String,
+    #[serde(default)]
+    pub optional: bool,
+    /// The crate depended on, if renamed.
+    #[serde(default)]
+    pub package: Option<String>,
+}
+
+impl Crate {
+    /// Features including the implicit ones of optional dependencies, which a crate only gets for
+    /// dependencies none of its features refer to as `dep:name`.
+    pub fn all_features(&self) -> HashMap<String, Vec<String>> {
+        let mut features = (*self.features).clone();
+        let explicit: Vec<&str> = self
+            .features
+            .values()
+            .flatten()
+            .filter_map(|enable| enable.strip_prefix("dep:"))
+            .collect();
+        for dep in self.deps.iter().filter(|dep| dep.optional) {
+            if !explicit.contains(&dep.name.as_str()) {
+                features
+                    .entry(dep.name.clone())
+                    .or_insert_with(|| vec![format!("dep:{}", dep.name)]);
+            }
+        }
+        features
+    }
+}
+
+/// Directory holding the indexes of all registries cargo has used.
+pub fn index_root() -> io::Result<PathBuf> {
+    Ok(config::cargo_home()?.join("registry").join("index"))
+}
+
+/// Any registry index directory cargo has fetched.
+pub fn default_index_dir() -> Result<PathBuf> {
+    let root = index_root()?;
+    root.read_dir()
+        .map_err(|_| Error::IndexMissing(root.clone()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.is_dir())
+        .ok_or(Error::IndexMissing(root))
+}
+
 impl CratesIndex {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            path: long_path(path),
+        }
+    }
+    /// Index of a directory created by `cargo local-registry`.
+    pub fn local_registry(path: &Path) -> Self {
+        Self::new(path.join("index"))
     }
     pub fn crates_with_prefix(&self, prefix: &str) -> io::Result<Vec<CrateMeta>> {
-        _crates_with_prefix(&self.path, &regexify(prefix), prefix)
+        _crates_with_prefix(&self.path, &regexify(prefix), prefix, None)
     }
     pub fn crate_(&self, name: &str) -> io::Result<Option<CrateMeta>> {
+        // Names of up to three characters are kept apart in `1/`, `2/` and `3/<first>/`.
+        if name.len() <= 3 {
+            let entry = entry_path(name);
+            let bucket = entry.rsplit_once('/').map_or("", |(bucket, _)| bucket);
+            return _crate_exact(&join_entry(&self.path, bucket), name, "");
+        }
         _crate_exact(&self.path, name, name)
     }
 }
 
-fn regexify(partial_name: &str) -> Regex {
-    Regex::new(
-        format!(
-            "^{}",
-            partial_name.replace("-", "?[-_]").replace("_", "?[-_]")
-        )
-        .as_str(),
-    )
-    .unwrap()
+impl IndexBackend for CratesIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        self.crates_with_prefix_until(prefix, None)
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        let start = Instant::now();
+        let crates = _crates_with_prefix(&self.path, &regexify(prefix), prefix, deadline)?;
+        debug!(
+            path = %self.path.display(),
+            prefix,
+            crates = crates.len(),
+            elapsed = ?start.elapsed(),
+            "walked index"
+        );
+        Ok(crates.into_iter().map(|crate_| crate_.name).collect())
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        self.crate_(name)?.map(|crate_| crate_.detail()).transpose()
+    }
+    fn state(&self) -> Option<String> {
+        match git2::Repository::open(&self.path) {
+            // A checkout is at a commit.
+            Ok(repo) => Some(repo.head().ok()?.target()?.to_string()),
+            // Cargo adds entries to its caches as files, touching the directories holding them.
+            Err(_) => tree_state(&self.path, usize::MAX),
+        }
+    }
+}
+
+/// A path that may exceed `MAX_PATH`, which Windows only allows in the verbatim `\\?\` form.
+#[cfg(windows)]
+fn long_path(path: PathBuf) -> PathBuf {
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+#[cfg(not(windows))]
+fn long_path(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// How many directories there are down to `depth` levels below `path` and when the last of
+/// them changed.
+pub(crate) fn tree_state(path: &Path, depth: usize) -> Option<String> {
+    let mut count = 0;
+    let mut newest = SystemTime::UNIX_EPOCH;
+    let mut pending = vec![(path.to_path_buf(), 0)];
+    while let Some((dir, level)) = pending.pop() {
+        count += 1;
+        newest = newest.max(fs::metadata(&dir).ok()?.modified().ok()?);
+        if level < depth {
+            for entry in fs::read_dir(&dir).ok()?.flatten() {
+                if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                    pending.push((entry.path(), level + 1));
+                }
+            }
+        }
+    }
+    let newest = newest.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+    Some(format!("{}:{}", count, newest.as_nanos()))
+}
+
+/// Path of a crate's entry relative to the index root, using `/` as separator.
+pub fn entry_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    // Crate names are ASCII, but a typed name may not be, so don't split inside a character.
+    let chars: Vec<char> = name.chars().collect();
+    let part = |range: std::ops::Range<usize>| chars[range].iter().collect::<String>();
+    match chars.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", part(0..1), name),
+        _ => format!("{}/{}/{}", part(0..2), part(2..4), name),
+    }
+}
+
+/// Whether `name` is made of the characters of crate names only, so it can name a file in a
+/// directory without leaving it.
+pub fn is_crate_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '_'))
+}
+
+/// `path` joined with the `/`-separated `entry`, one part at a time as verbatim Windows paths
+/// don't know `/`.
+fn join_entry(path: &Path, entry: &str) -> PathBuf {
+    entry
+        .split('/')
+        .fold(path.to_path_buf(), |path, part| path.join(part))
+}
+
+pub(crate) fn regexify(partial_name: &str) -> Regex {
+    let pattern: String = partial_name
+        .chars()
+        .map(|char| match char {
+            '-' | '_' => "?[-_]".to_string(),
+            _ => regex::escape(char.encode_utf8(&mut [0; 4])),
+        })
+        .collect();
+    Regex::new(&format!("^{}", pattern)).expect("escaped prefix is a valid pattern")
+}
+
+/// Name of a directory entry, or `None` for names that can't be a crate or a bucket of the index:
+/// those that aren't UTF-8, and those with a `.` like `config.json` or `.git`.
+fn entry_name(entry: &fs::DirEntry) -> Option<String> {
+    let name = entry.file_name().into_string().ok();
+    if name.is_none() {
+        trace!(path = %entry.path().display(), "skipping non-utf-8 index entry");
+    }
+    name.filter(|name| !name.contains('.'))
 }
 
 fn expand_path_domain(part: &str) -> impl Iterator<Item = String> {
@@ -97,19 +511,22 @@ pub fn _crate_exact(
     prefix: &str,
     remaining_prefix: &str,
 ) -> io::Result<Option<CrateMeta>> {
-    if !path.is_dir() {
+    // Buckets are named after the first bytes of ASCII names, which other names can't have.
+    if !path.is_dir() || !remaining_prefix.is_ascii() {
         return Ok(None);
     }
 
     let crate_ = path
         .read_dir()?
         .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_name().to_str().unwrap() == prefix)
-        .map(|entry| CrateMeta {
-            name: entry.file_name().into_string().unwrap(),
-            path: entry.path(),
-        })
-        .next();
+        .find_map(|entry| {
+            // Entries are lowercase, but Windows and macOS don't care how older ones are cased.
+            let name = entry_name(&entry).filter(|name| name.eq_ignore_ascii_case(prefix))?;
+            Some(CrateMeta {
+                name,
+                path: entry.path(),
+            })
+        });
 
     if let Some(crate_) = crate_ {
         Ok(Some(crate_))
@@ -119,86 +536,203 @@ pub fn _crate_exact(
             1 => _crate_exact(path, prefix, ""),
             _ => {
                 let (h1, t1) = remaining_prefix.split_at(1);
+                let h1 = h1.to_ascii_lowercase();
                 if let Some(crate_) = _crate_exact(&path.join(h1), prefix, t1)? {
                     return Ok(Some(crate_));
                 }
 
                 let (h2, t2) = remaining_prefix.split_at(2);
-                _crate_exact(&path.join(h2), prefix, t2)
+                _crate_exact(&path.join(h2.to_ascii_lowercase()), prefix, t2)
             }
         }
     }
 }
 
+/// Crates of the index at `path` whose names start with `prefix`, as `matcher` matches them.
 pub fn _crates_with_prefix(
+    path: &Path,
+    matcher: &Regex,
+    prefix: &str,
+    deadline: Option<&Deadline>,
+) -> io::Result<Vec<CrateMeta>> {
+    let mut crates = walk_buckets(path, matcher, prefix, deadline)?;
+    // Names of up to three characters are kept apart in `1/`, `2/` and `3/<first>/`, which the
+    // buckets named after the prefix leave out. All of them are walked for an empty prefix.
+    if !prefix.is_empty() && prefix.is_ascii() {
+        let mut buckets = vec![];
+        if prefix.len() <= 1 {
+            buckets.push(path.join("1"));
+        }
+        if prefix.len() <= 2 {
+            buckets.push(path.join("2"));
+        }
+        if prefix.len() <= 3 {
+            buckets.extend(
+                expand_path_domain(&prefix[..1].to_ascii_lowercase())
+                    .map(|first| path.join("3").join(first)),
+            );
+        }
+        for bucket in buckets {
+            crates.extend(walk_buckets(&bucket, matcher, "", deadline)?);
+        }
+    }
+    Ok(crates)
+}
+
+/// Crates in `path` and the buckets below it that may hold names continuing with
+/// `remaining_prefix`.
+fn walk_buckets(
     path: &Path,
     matcher: &Regex,
     remaining_prefix: &str,
+    deadline: Option<&Deadline>,
 ) -> io::Result<Vec<CrateMeta>> {
-    if !path.is_dir() {
+    if !path.is_dir() || !remaining_prefix.is_ascii() || deadline.is_some_and(Deadline::expired) {
         return Ok(vec![]);
     }
+    trace!(dir = %path.display(), "scanning index directory");
 
-    let path_iter = path
+    let mut crates = path
         .read_dir()?
         .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().map(|ft| ft.is_file()).unwrap_or(false));
-    let mut crates = if remaining_prefix.is_empty() {
-        Either::Left(path_iter)
-    } else {
-        Either::Right(
-            path_iter.filter(|entry| matcher.is_match(entry.file_name().to_str().unwrap())),
-        )
-    }
-    .map(|entry| CrateMeta {
-        name: entry.file_name().into_string().unwrap(),
-        path: entry.path(),
-    })
-    .collect_vec();
-
-    match remaining_prefix.len() {
-        0 => {
-            for path in path
-                .read_dir()?
-                .filter_map(|entry| entry.ok())
-                .filter(|entry| entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
-            {
-                crates.extend(_crates_with_prefix(&path.path(), matcher, "")?);
-            }
-            Ok(crates)
-        }
+        .filter(|entry| entry.file_type().map(|ft| ft.is_file()).unwrap_or(false))
+        .filter_map(|entry| {
+            let name = entry_name(&entry)?;
+            matcher.is_match(&name).then(|| CrateMeta {
+                name,
+                path: entry.path(),
+            })
+        })
+        .collect_vec();
+
+    // Subdirectories to walk along with the prefix left to match in them. Buckets are named in
+    // lowercase, which only case-insensitive filesystems would find for other prefixes.
+    let subdirs: Vec<(PathBuf, &str)> = match remaining_prefix.len() {
+        0 => path
+            .read_dir()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
+            .map(|entry| (entry.path(), ""))
+            .collect(),
         1 => {
-            let parts = expand_path_domain(remaining_prefix).collect_vec();
+            let parts = expand_path_domain(&remaining_prefix.to_ascii_lowercase()).collect_vec();
+            let mut subdirs = vec![];
             for subpath in path
                 .read_dir()?
                 .filter_map(|entry| entry.ok())
                 .filter(|entry| entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
             {
-                let subpath = subpath.path();
+                let Some(name) = entry_name(&subpath) else {
+                    continue;
+                };
                 for part in &parts {
-                    if subpath
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .starts_with(part)
-                    {
-                        crates.extend(_crates_with_prefix(&subpath, matcher, "")?);
+                    if name.to_ascii_lowercase().starts_with(part) {
+                        subdirs.push((subpath.path(), ""));
                     }
                 }
             }
-            Ok(crates)
+            subdirs
         }
         _ => {
             let (h1, t1) = remaining_prefix.split_at(1);
             let (h2, t2) = remaining_prefix.split_at(2);
-            for path in expand_path_domain(h1).map(|h1| path.join(h1)) {
-                crates.extend(_crates_with_prefix(&path, matcher, t1)?);
-            }
-            for path in expand_path_domain(h2).map(|h2| path.join(h2)) {
-                crates.extend(_crates_with_prefix(&path, matcher, t2)?);
-            }
-            Ok(crates)
+            expand_path_domain(&h1.to_ascii_lowercase())
+                .map(|h1| (path.join(h1), t1))
+                .chain(expand_path_domain(&h2.to_ascii_lowercase()).map(|h2| (path.join(h2), t2)))
+                .collect()
+        }
+    };
+    // Buckets are independent, so walk them in parallel to not wait on the disk one at a time.
+    let nested: Vec<Vec<CrateMeta>> = subdirs
+        .par_iter()
+        .map(|(subdir, remaining)| walk_buckets(subdir, matcher, remaining, deadline))
+        .collect::<io::Result<_>>()?;
+    crates.extend(nested.into_iter().flatten());
+    Ok(crates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = r#"{"name":"foo","vers":"1.0.0","deps":[],"features":{},"yanked":false}"#;
+
+    /// A cache file holding `entries`, with the given cache and index format versions.
+    fn cache_file(cache_version: u8, index_v: u32, entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut content = vec![cache_version];
+        content.extend(index_v.to_le_bytes());
+        content.extend(b"etag\0");
+        for (version, entry) in entries {
+            content.extend(version.as_bytes());
+            content.push(0);
+            content.extend(entry.as_bytes());
+            content.push(0);
+        }
+        content
+    }
+
+    #[test]
+    fn crate_names() {
+        for name in ["serde", "serde_json", "tokio-serial", "a", "3d"] {
+            assert!(is_crate_name(name), "{}", name);
+        }
+        for name in [
+            "",
+            "..",
+            "../serde",
+            "a/b",
+            r"a\b",
+            "serde.json",
+            "séance",
+            "a b",
+        ] {
+            assert!(!is_crate_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn parse_cache_entries() {
+        let entry2 = ENTRY.replace("1.0.0", "1.1.0");
+        for index_v in 0..=INDEX_V_MAX {
+            let content = cache_file(
+                CACHE_VERSION,
+                index_v,
+                &[("1.0.0", ENTRY), ("1.1.0", &entry2)],
+            );
+            let versions = parse_cache(&content).unwrap();
+            let versions = versions.iter().map(|crate_| crate_.version.as_str());
+            assert_eq!(versions.collect_vec(), ["1.0.0", "1.1.0"]);
+        }
+    }
+
+    #[test]
+    fn parse_cache_without_entries() {
+        let content = cache_file(CACHE_VERSION, 1, &[]);
+        assert!(parse_cache(&content).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_cache_rejects_unknown_versions() {
+        let content = cache_file(CACHE_VERSION + 1, 1, &[("1.0.0", ENTRY)]);
+        assert!(matches!(parse_cache(&content), Err(Error::IndexFormat(_))));
+        let content = cache_file(CACHE_VERSION, INDEX_V_MAX + 1, &[("1.0.0", ENTRY)]);
+        assert!(matches!(parse_cache(&content), Err(Error::IndexFormat(_))));
+    }
+
+    #[test]
+    fn parse_cache_rejects_truncated_files() {
+        let content = cache_file(CACHE_VERSION, 1, &[("1.0.0", ENTRY)]);
+        for len in 0..5 {
+            assert!(matches!(
+                parse_cache(&content[..len]),
+                Err(Error::IndexFormat(_))
+            ));
         }
+        // Cut within the entry, leaving malformed JSON as the only entry.
+        let len = content.len() - 10;
+        assert!(matches!(
+            parse_cache(&content[..len]),
+            Err(Error::IndexEntry(_))
+        ));
     }
 }
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A point in time after which completions stop looking for candidates and make do with the ones
+/// found so far, or a flag telling them to stop whenever it is set.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    at: Option<Instant>,
+    /// Set by a frontend abandoning the completion, e.g. because the user typed on.
+    cancelled: Option<Arc<AtomicBool>>,
+    /// Whether something stopped early because of the deadline, shared by all clones.
+    cut: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    pub fn after(budget: Duration) -> Self {
+        Self {
+            at: Some(Instant::now() + budget),
+            cancelled: None,
+            cut: Arc::default(),
+        }
+    }
+
+    /// A deadline only passing once `cancelled` is set.
+    pub fn cancelled_by(cancelled: Arc<AtomicBool>) -> Self {
+        Self {
+            at: None,
+            cancelled: Some(cancelled),
+            cut: Arc::default(),
+        }
+    }
+
+    /// Also pass the deadline once `cancelled` is set.
+    pub fn or_cancelled_by(self, cancelled: Arc<AtomicBool>) -> Self {
+        Self {
+            cancelled: Some(cancelled),
+            ..self
+        }
+    }
+
+    /// Whether the time is up or the completion was cancelled, remembering that candidates may
+    /// be missing if so.
+    pub fn expired(&self) -> bool {
+        let expired = self.at.is_some_and(|at| Instant::now() >= at)
+            || self
+                .cancelled
+                .as_ref()
+                .is_some_and(|cancelled| cancelled.load(Ordering::Relaxed));
+        if expired {
+            self.cut();
+        }
+        expired
+    }
+
+    /// Time left until the deadline, `None` if it only passes once cancelled.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Remember that candidates may be missing, e.g. when another process cut its search short.
+    pub fn cut(&self) {
+        self.cut.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether some search stopped early because the time was up.
+    pub fn cut_short(&self) -> bool {
+        self.cut.load(Ordering::Relaxed)
+    }
+}
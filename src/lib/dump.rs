@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use flate2::read::GzDecoder;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+use crate::cache;
+use crate::error::{Error, Result};
+use crate::net::{self, Service};
+use crate::{Candidate, Kind};
+
+/// The daily database dump of crates.io, see <https://crates.io/data-access>.
+pub const DUMP_URL: &str = "https://static.crates.io/db-dump.tar.gz";
+
+const SCHEMA: &str = "
+    CREATE TABLE crates (name TEXT PRIMARY KEY, description TEXT, downloads INTEGER NOT NULL);
+    CREATE TABLE keywords (crate TEXT NOT NULL, keyword TEXT NOT NULL);
+    CREATE TABLE categories (crate TEXT NOT NULL, category TEXT NOT NULL);
+    CREATE INDEX keywords_keyword ON keywords (keyword);
+    CREATE INDEX categories_category ON categories (category);
+    CREATE VIRTUAL TABLE descriptions USING fts5(
+        name, description, content = 'crates', tokenize = 'porter unicode61'
+    );
+";
+
+/// How many of the most relevant full-text matches to rank by downloads too.
+const FULL_TEXT_LIMIT: usize = 200;
+
+/// Where `import-dump` puts the database by default.
+pub fn default_path() -> Option<PathBuf> {
+    Some(cache::cache_dir()?.join("db-dump.sqlite"))
+}
+
+/// Collapse the line breaks and indentation descriptions often have.
+fn one_line(description: &str) -> String {
+    description.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Crate metadata imported from the crates.io database dump.
+#[derive(Debug, Clone)]
+pub struct Dump {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Dump {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Open the database at the default path, if it was ever imported.
+    pub fn open_default() -> Option<Self> {
+        let path = default_path()?;
+        path.is_file().then(|| Self::open(&path).ok()).flatten()
+    }
+
+    /// All-time download counts of the crates starting with a partial name, by crate name.
+    pub fn downloads(&self, partial_name: &str) -> Result<HashMap<String, u64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT name, downloads FROM crates WHERE name LIKE ?1")?;
+        // `_` matches any character in LIKE, so `-` and `_` match each other like on crates.io.
+        let pattern = format!("{}%", partial_name.replace('-', "_"));
+        let rows = stmt.query_map(params![pattern], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The one-line description of a crate, if it has one.
+    pub fn description(&self, crate_name: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let description: Option<Option<String>> = conn
+            .query_row(
+                "SELECT description FROM crates WHERE name = ?1",
+                params![crate_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(description
+            .flatten()
+            .map(|description| one_line(&description))
+            .filter(|description| !description.is_empty()))
+    }
+
+    /// Crates starting with a partial name that have a keyword and are in a category, both
+    /// optional, most downloaded first and with their descriptions.
+    ///
+    /// A category includes its subcategories, e.g. `development-tools` has
+    /// `development-tools::testing`.
+    pub fn search(
+        &self,
+        keyword: Option<&str>,
+        category: Option<&str>,
+        partial_name: &str,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT name, description FROM crates
+             WHERE name LIKE ?1
+               AND (?2 IS NULL OR name IN (SELECT crate FROM keywords WHERE keyword = ?2))
+               AND (?3 IS NULL OR name IN (SELECT crate FROM categories
+                                           WHERE category = ?3 OR category LIKE ?3 || '::%'))
+             ORDER BY downloads DESC",
+        )?;
+        let pattern = format!("{}%", partial_name.replace('-', "_"));
+        let rows = stmt.query_map(params![pattern, keyword, category], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Crates whose name or description match all words of a query, each weighted by text
+    /// relevance and downloads, best first. The last word may be partial.
+    pub fn full_text(&self, query: &str) -> Result<Vec<(String, Option<String>, f64)>> {
+        let words: Vec<_> = query.split_whitespace().collect();
+        let query = match words.split_last() {
+            Some((last, rest)) => rest
+                .iter()
+                .map(|word| fts_string(word))
+                .chain(std::iter::once(format!("{}*", fts_string(last))))
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => return Ok(vec![]),
+        };
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT crates.name, crates.description, crates.downloads, bm25(descriptions)
+             FROM descriptions JOIN crates ON crates.rowid = descriptions.rowid
+             WHERE descriptions MATCH ?1
+             ORDER BY bm25(descriptions)
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![query, FULL_TEXT_LIMIT as i64], |row| {
+            let downloads: i64 = row.get(2)?;
+            // bm25 is negative, lower meaning more relevant.
+            let relevance: f64 = -row.get::<_, f64>(3)?;
+            let score = relevance * (std::f64::consts::E + downloads as f64).ln();
+            Ok((row.get(0)?, row.get(1)?, score))
+        })?;
+        let mut matches = rows.collect::<Result<Vec<_>, _>>()?;
+        matches.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        Ok(matches)
+    }
+}
+
+/// Quote a word so FTS5 treats it as a plain string rather than query syntax.
+fn fts_string(word: &str) -> String {
+    format!("\"{}\"", word.replace('"', "\"\""))
+}
+
+/// Complete crate names matching a full-text query over their names and descriptions.
+pub fn complete_full_text(dump: &Dump, query: &str) -> Result<Vec<Candidate>> {
+    Ok(dump
+        .full_text(query)?
+        .into_iter()
+        .map(|(name, description, _)| Candidate {
+            value: name,
+            display: None,
+            description: description.map(|description| one_line(&description)),
+            score: None,
+            kind: Kind::Crate,
+            yanked: false,
+        })
+        .collect())
+}
+
+/// Complete crate names by keyword or category rather than by name alone, for discovering crates.
+pub fn complete_search(
+    dump: &Dump,
+    keyword: Option<&str>,
+    category: Option<&str>,
+    partial_name: &str,
+) -> Result<Vec<Candidate>> {
+    Ok(dump
+        .search(keyword, category, partial_name)?
+        .into_iter()
+        .map(|(name, description)| Candidate {
+            value: name,
+            display: None,
+            description: description.map(|description| one_line(&description)),
+            score: None,
+            kind: Kind::Crate,
+            yanked: false,
+        })
+        .collect())
+}
+
+/// Rows of a csv table, keeping only the given columns; missing ones read as empty.
+fn read_table(reader: impl Read, columns: &[&str]) -> Result<Vec<Vec<String>>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let indices: Vec<_> = columns
+        .iter()
+        .map(|column| headers.iter().position(|header| header == *column))
+        .collect();
+    reader
+        .records()
+        .map(|record| {
+            let record = record?;
+            Ok(indices
+                .iter()
+                .map(|index| {
+                    index
+                        .and_then(|index| record.get(index))
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect())
+        })
+        .collect()
+}
+
+/// The tables of the dump the completer uses, as read from its csv files.
+#[derive(Default)]
+struct Tables {
+    /// `id, name, description, downloads`; older dumps keep the downloads here.
+    crates: Vec<Vec<String>>,
+    /// `crate_id, downloads`
+    crate_downloads: Vec<Vec<String>>,
+    /// `id, keyword`
+    keywords: Vec<Vec<String>>,
+    /// `crate_id, keyword_id`
+    crates_keywords: Vec<Vec<String>>,
+    /// `id, slug`
+    categories: Vec<Vec<String>>,
+    /// `crate_id, category_id`
+    crates_categories: Vec<Vec<String>>,
+}
+
+fn read_tables(archive: impl Read) -> Result<Tables> {
+    let mut tables = Tables::default();
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    for entry in archive.entries().map_err(Error::DumpIo)? {
+        let entry = entry.map_err(Error::DumpIo)?;
+        let path = entry.path().map_err(Error::DumpIo)?.into_owned();
+        // Tables live in `<timestamp>/data/<table>.csv`.
+        if path.parent().and_then(Path::file_name) != Some("data".as_ref()) {
+            continue;
+        }
+        let (table, columns): (_, &[&str]) = match path.file_name().and_then(|name| name.to_str()) {
+            Some("crates.csv") => (
+                &mut tables.crates,
+                &["id", "name", "description", "downloads"],
+            ),
+            Some("crate_downloads.csv") => {
+                (&mut tables.crate_downloads, &["crate_id", "downloads"])
+            }
+            Some("keywords.csv") => (&mut tables.keywords, &["id", "keyword"]),
+            Some("crates_keywords.csv") => {
+                (&mut tables.crates_keywords, &["crate_id", "keyword_id"])
+            }
+            Some("categories.csv") => (&mut tables.categories, &["id", "slug"]),
+            Some("crates_categories.csv") => {
+                (&mut tables.crates_categories, &["crate_id", "category_id"])
+            }
+            _ => continue,
+        };
+        *table = read_table(entry, columns)?;
+    }
+    if tables.crates.is_empty() {
+        return Err(Error::DumpFormat(
+            "archive contains no crates.csv, is it a crates.io db dump?".to_string(),
+        ));
+    }
+    Ok(tables)
+}
+
+/// Map the first column of a table to its second one.
+fn by_id(table: &[Vec<String>]) -> HashMap<&str, &str> {
+    table
+        .iter()
+        .map(|row| (row[0].as_str(), row[1].as_str()))
+        .collect()
+}
+
+/// Build the database at `path` from a `db-dump.tar.gz` archive, returning how many crates it has.
+///
+/// The database is replaced only once the import succeeded, so completion keeps working meanwhile.
+pub fn import(archive: impl Read, path: &Path) -> Result<usize> {
+    let tables = read_tables(archive)?;
+    let names = by_id(&tables.crates);
+    let downloads = by_id(&tables.crate_downloads);
+    let keywords = by_id(&tables.keywords);
+    let categories = by_id(&tables.categories);
+
+    let written = |source| Error::FileWrite {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(written)?;
+    }
+    let tmp = path.with_extension("sqlite.tmp");
+    let _ = fs::remove_file(&tmp);
+    let mut conn = Connection::open(&tmp)?;
+    conn.execute_batch(SCHEMA)?;
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare("INSERT INTO crates VALUES (?1, ?2, ?3)")?;
+        for row in &tables.crates {
+            let count = downloads
+                .get(row[0].as_str())
+                .copied()
+                .unwrap_or(row[3].as_str());
+            let description = Some(row[2].as_str()).filter(|description| !description.is_empty());
+            insert.execute(params![
+                row[1],
+                description,
+                count.parse::<i64>().unwrap_or(0)
+            ])?;
+        }
+        let mut insert = tx.prepare("INSERT INTO keywords VALUES (?1, ?2)")?;
+        for row in &tables.crates_keywords {
+            if let (Some(name), Some(keyword)) =
+                (names.get(row[0].as_str()), keywords.get(row[1].as_str()))
+            {
+                insert.execute(params![name, keyword])?;
+            }
+        }
+        let mut insert = tx.prepare("INSERT INTO categories VALUES (?1, ?2)")?;
+        for row in &tables.crates_categories {
+            if let (Some(name), Some(category)) =
+                (names.get(row[0].as_str()), categories.get(row[1].as_str()))
+            {
+                insert.execute(params![name, category])?;
+            }
+        }
+    }
+    tx.execute_batch("INSERT INTO descriptions (descriptions) VALUES ('rebuild')")?;
+    tx.commit()?;
+    drop(conn);
+    fs::rename(&tmp, path).map_err(written)?;
+    Ok(tables.crates.len())
+}
+
+/// Download the latest dump from crates.io, streaming it as it arrives.
+pub fn download() -> Result<impl Read> {
+    net::check(Service::Dump)?;
+    Ok(net::agent(DUMP_URL)?.get(DUMP_URL).call()?.into_reader())
+}
@@ -0,0 +1,126 @@
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::net::Service;
+
+/// Why a completion failed, for consumers to tell causes apart.
+///
+/// Errors caused by others tell the cause as their [source](std::error::Error::source) rather
+/// than in their message.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The index doesn't know the crate.
+    #[error("crate `{0}` is not in the index")]
+    CrateNotFound(String),
+    /// No version of the crate satisfies the typed requirement.
+    #[error("no version of `{name}` matches `{req}`")]
+    VersionNotFound { name: String, req: String },
+    /// The crate doesn't depend on the named crate.
+    #[error("`{name}` has no dependency `{dependency}`")]
+    DependencyNotFound { name: String, dependency: String },
+    /// A typed version or version requirement is malformed.
+    #[error("invalid version")]
+    VersionParse(#[from] semver::Error),
+    /// A pattern typed under `--match regex` is malformed.
+    #[error("invalid pattern")]
+    Pattern(#[from] regex::Error),
+    /// Reading the index failed.
+    #[error("failed to read the index")]
+    IndexIo(#[from] io::Error),
+    /// No registry index was fetched into the directory.
+    #[error("no registry index found in {}", .0.display())]
+    IndexMissing(PathBuf),
+    /// An index entry is no valid JSON.
+    #[error("malformed index entry")]
+    IndexEntry(#[from] serde_json::Error),
+    /// An index file is in a format this version doesn't understand.
+    #[error("unsupported index file: {0}")]
+    IndexFormat(String),
+    /// The git repository of an index can't be read.
+    #[error("failed to read the git index")]
+    Git(#[from] git2::Error),
+    /// Cargo's configuration names no index for the registry.
+    #[error("registry `{0}` is not configured")]
+    RegistryNotConfigured(String),
+    /// Source replacement of the registry leads back to itself.
+    #[error("source replacement of `{0}` is cyclic")]
+    CyclicReplacement(String),
+    /// Cargo never fetched the index of the registry at the url.
+    #[error("index of registry {0} has not been fetched yet")]
+    IndexNotFetched(String),
+    /// A configuration file, manifest or lockfile can't be read.
+    #[error("failed to read {}", .path.display())]
+    FileRead { path: PathBuf, source: io::Error },
+    /// A cache or the history can't be written.
+    #[error("failed to write {}", .path.display())]
+    FileWrite { path: PathBuf, source: io::Error },
+    /// A configuration file, manifest or lockfile is no valid TOML.
+    #[error("malformed TOML in {}", .path.display())]
+    Toml {
+        path: PathBuf,
+        source: Box<toml::de::Error>,
+    },
+    /// An entry of the `deny` or `allow` list of the configuration is no valid pattern.
+    #[error("invalid `{key}` entry `{pattern}`")]
+    FilterPattern {
+        key: String,
+        pattern: String,
+        source: regex::Error,
+    },
+    /// No `Cargo.toml` was found for a completion that reads one.
+    #[error("could not find Cargo.toml in {} or its parents", .0.display())]
+    ManifestMissing(PathBuf),
+    /// Reaching a service over the network is disabled, by `--offline` or the settings.
+    #[error("network access to {0} is disabled")]
+    NetworkDisabled(Service),
+    /// A request over the network failed.
+    #[error("request failed")]
+    Http(#[source] Box<ureq::Error>),
+    /// A service answered in a way it shouldn't have.
+    #[error("unexpected response: {0}")]
+    Response(String),
+    /// The body of a response can't be read.
+    #[error("failed to read the response")]
+    ResponseBody(#[source] io::Error),
+    /// The crates.io API answered with malformed JSON.
+    #[error("malformed API response")]
+    ApiResponse(#[source] serde_json::Error),
+    /// `git ls-remote` couldn't be run.
+    #[error("failed to run git")]
+    GitSpawn(#[source] io::Error),
+    /// `git ls-remote` failed or took too long.
+    #[error("git ls-remote {url} {reason}")]
+    GitRemote { url: String, reason: String },
+    /// The database dump can't be queried or written.
+    #[error("failed to access the database dump")]
+    Dump(#[from] rusqlite::Error),
+    /// The downloaded database dump can't be read.
+    #[error("failed to read the database dump")]
+    DumpIo(#[source] io::Error),
+    /// A table of the database dump is malformed.
+    #[error("malformed database dump")]
+    DumpCsv(#[from] csv::Error),
+    /// The database dump lacks what completions need.
+    #[error("unsupported database dump: {0}")]
+    DumpFormat(String),
+    /// The names cached by `warm` can't be built or read.
+    #[error("malformed crate name cache")]
+    NameCache(#[from] fst::Error),
+    /// Neither a cache nor a data directory is known, as `HOME` isn't set.
+    #[error("no cache or data directory, as HOME is not set")]
+    NoHome,
+    /// A task looking up crates panicked or was cancelled.
+    #[cfg(feature = "async")]
+    #[error("lookup task failed")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+impl From<ureq::Error> for Error {
+    fn from(err: ureq::Error) -> Self {
+        Error::Http(Box::new(err))
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
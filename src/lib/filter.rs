@@ -0,0 +1,51 @@
+use regex::Regex;
+
+use crate::error::{Error, Result};
+
+/// Table of `.cargo/config.toml` holding the settings of the completer.
+pub const CONFIG_TABLE: &str = "cargo-edit-completion";
+
+/// Crates to always hide or always show, configured as `deny` and `allow` lists in the settings
+/// or under `[cargo-edit-completion]` in `.cargo/config.toml`, e.g. to keep banned crates out of
+/// sight.
+///
+/// Entries are regular expressions matching whole crate names, so plain names work as well.
+#[derive(Debug, Clone, Default)]
+pub struct CrateFilter {
+    deny: Vec<Regex>,
+    allow: Vec<Regex>,
+}
+
+fn patterns(patterns: &[String], key: &str) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(&format!("^(?:{})$", pattern)).map_err(|source| Error::FilterPattern {
+                key: key.to_string(),
+                pattern: pattern.clone(),
+                source,
+            })
+        })
+        .collect()
+}
+
+impl CrateFilter {
+    pub fn new(deny: &[String], allow: &[String]) -> Result<Self> {
+        Ok(Self {
+            deny: patterns(deny, "deny")?,
+            allow: patterns(allow, "allow")?,
+        })
+    }
+
+    /// Whether a crate is allowed explicitly, exempting it from the deny list and other filters.
+    pub fn allows(&self, crate_name: &str) -> bool {
+        self.allow
+            .iter()
+            .any(|pattern| pattern.is_match(crate_name))
+    }
+
+    /// Whether a crate must never be shown.
+    pub fn denies(&self, crate_name: &str) -> bool {
+        !self.allows(crate_name) && self.deny.iter().any(|pattern| pattern.is_match(crate_name))
+    }
+}
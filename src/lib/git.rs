@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use git2::{ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use tracing::debug;
+
+use crate::backend::IndexBackend;
+use crate::crates::{entry_path, parse_lines, regexify, Crate};
+use crate::deadline::Deadline;
+use crate::error::{Error, Result};
+
+const HEAD: &str = "refs/remotes/origin/HEAD";
+
+/// A registry index read straight from git objects, without a checked-out working tree.
+pub struct GitIndex {
+    repo: Repository,
+}
+
+impl GitIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            repo: Repository::open(path)?,
+        })
+    }
+
+    fn tree(&self) -> Result<Tree<'_>> {
+        Ok(self.repo.find_reference(HEAD)?.peel_to_tree()?)
+    }
+
+    fn read_blob(&self, oid: Oid) -> Result<String> {
+        let blob = self.repo.find_blob(oid)?;
+        String::from_utf8(blob.content().to_vec())
+            .map_err(|_| Error::IndexFormat("index file is not utf-8".to_string()))
+    }
+}
+
+impl IndexBackend for GitIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        self.crates_with_prefix_until(prefix, None)
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        let matcher = regexify(prefix);
+        let mut crates = vec![];
+        let mut stopped = false;
+        let walked = self.tree()?.walk(TreeWalkMode::PreOrder, |root, entry| {
+            let name = match entry.name() {
+                Some(name) => name,
+                None => return TreeWalkResult::Skip,
+            };
+            match entry.kind() {
+                Some(ObjectType::Tree) if deadline.is_some_and(Deadline::expired) => {
+                    stopped = true;
+                    TreeWalkResult::Abort
+                }
+                Some(ObjectType::Tree) => {
+                    if bucket_matches(root, name, prefix) {
+                        TreeWalkResult::Ok
+                    } else {
+                        TreeWalkResult::Skip
+                    }
+                }
+                Some(ObjectType::Blob) => {
+                    // `config.json` lives at the root, crate files only in buckets.
+                    if !root.is_empty() && matcher.is_match(name) {
+                        crates.push(name.to_string());
+                    }
+                    TreeWalkResult::Ok
+                }
+                _ => TreeWalkResult::Skip,
+            }
+        });
+        debug!(prefix, crates = crates.len(), stopped, "walked git index");
+        // Aborting the walk fails it, but the crates found before are still good.
+        if !stopped {
+            walked?;
+        }
+        Ok(crates)
+    }
+
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        match self.tree()?.get_path(Path::new(&entry_path(name))) {
+            Ok(entry) => Ok(Some(parse_lines(&self.read_blob(entry.id())?)?)),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn state(&self) -> Option<String> {
+        let commit = self.repo.find_reference(HEAD).ok()?.peel_to_commit().ok()?;
+        Some(commit.id().to_string())
+    }
+}
+
+/// Whether the bucket directory `name` under `root` may contain crates starting with `prefix`.
+fn bucket_matches(root: &str, name: &str, prefix: &str) -> bool {
+    let offset = match root {
+        "" if matches!(name, "1" | "2" | "3") => {
+            return name.parse::<usize>().unwrap() >= prefix.len()
+        }
+        "" | "3/" => 0,
+        _ => 2,
+    };
+    name.chars()
+        .zip(prefix.chars().skip(offset))
+        .all(|(a, b)| a == b || (matches!(a, '-' | '_') && matches!(b, '-' | '_')))
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use itertools::Itertools;
+
+use crate::error::{Error, Result};
+use crate::line::{parse, FEATURE_FLAGS};
+
+/// History entries of crates, valued by crate name.
+pub const CRATE: &str = "crate";
+/// History entries of features, valued by `crate:feature`.
+pub const FEATURE: &str = "feature";
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// File the crates and features the user picked are appended to, one `time<TAB>kind<TAB>value`
+/// line each.
+pub fn history_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(base.join(env!("CARGO_PKG_NAME")).join("history"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// How much a pick counts depending on how long ago it happened, like zoxide does.
+fn weight(age: u64) -> f64 {
+    match age {
+        age if age < HOUR => 4.0,
+        age if age < DAY => 2.0,
+        age if age < WEEK => 0.5,
+        _ => 0.25,
+    }
+}
+
+/// Frecency of the crates and features picked before, i.e. how often and how recently.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    scores: HashMap<(String, String), f64>,
+}
+
+impl History {
+    /// Load the history file, an absent one meaning nothing was picked yet.
+    pub fn load() -> Self {
+        let content = history_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let now = now();
+        let mut scores = HashMap::new();
+        for line in content.lines() {
+            if let Some((time, kind, value)) = line.splitn(3, '\t').collect_tuple() {
+                let age = now.saturating_sub(time.parse().unwrap_or_default());
+                *scores
+                    .entry((kind.to_string(), value.to_string()))
+                    .or_default() += weight(age);
+            }
+        }
+        Self { scores }
+    }
+
+    pub fn frecency(&self, kind: &str, value: &str) -> f64 {
+        self.scores
+            .get(&(kind.to_string(), value.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Move items picked before to the front, most frecent first, keeping the order of the
+    /// rest. `key` gives the history value of an item.
+    pub fn boost<T>(&self, kind: &str, key: impl Fn(&T) -> String, items: &mut [T]) {
+        items.sort_by(|a, b| {
+            self.frecency(kind, &key(b))
+                .total_cmp(&self.frecency(kind, &key(a)))
+        });
+    }
+}
+
+/// Append picks to the history file.
+pub fn record(entries: &[(&str, String)]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let path = history_path().ok_or(Error::NoHome)?;
+    let now = now();
+    let content: String = entries
+        .iter()
+        .map(|(kind, value)| format!("{}\t{}\t{}\n", now, kind, value))
+        .collect();
+    let append = || {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(content.as_bytes())
+    };
+    append().map_err(|source| Error::FileWrite {
+        path: path.clone(),
+        source,
+    })
+}
+
+/// The crates and features an executed `cargo add` picked; empty for other commands.
+pub fn picks(line: &str) -> Vec<(&'static str, String)> {
+    // Parse as if the cursor was after the command, so all its words count.
+    let line = format!("{} ", line);
+    let invocation = parse(&line);
+    if invocation.subcommand != Some("add") {
+        return vec![];
+    }
+    let crates: Vec<&str> = invocation
+        .specs
+        .iter()
+        .map(|spec| spec.split('@').next().unwrap_or(spec))
+        .collect();
+    let features = invocation
+        .values
+        .iter()
+        .filter(|(flag, _)| FEATURE_FLAGS.contains(flag))
+        .flat_map(|(_, list)| list.split([',', ' ']))
+        .filter(|feature| !feature.is_empty())
+        .filter_map(|feature| match feature.split_once('/') {
+            // `crate/feature` names the crate when adding several.
+            Some((crate_name, feature)) if crates.contains(&crate_name) => {
+                Some(format!("{}:{}", crate_name, feature))
+            }
+            _ => Some(format!("{}:{}", crates.last()?, feature)),
+        });
+    crates
+        .iter()
+        .map(|crate_name| (CRATE, crate_name.to_string()))
+        .chain(features.map(|feature| (FEATURE, feature)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_of_cargo_add() {
+        assert_eq!(
+            picks("cargo add serde --features derive"),
+            [
+                (CRATE, "serde".to_string()),
+                (FEATURE, "serde:derive".to_string())
+            ]
+        );
+        assert_eq!(
+            picks("cargo add serde@1.0 tokio -F serde/rc,full --dev"),
+            [
+                (CRATE, "serde".to_string()),
+                (CRATE, "tokio".to_string()),
+                (FEATURE, "serde:rc".to_string()),
+                (FEATURE, "tokio:full".to_string()),
+            ]
+        );
+        assert_eq!(
+            picks("cargo add --features=derive,rc serde"),
+            [
+                (CRATE, "serde".to_string()),
+                (FEATURE, "serde:derive".to_string()),
+                (FEATURE, "serde:rc".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_picks_of_other_commands() {
+        assert!(picks("cargo rm serde").is_empty());
+        assert!(picks("cargo build --features derive").is_empty());
+        assert!(picks("cargo add").is_empty());
+    }
+}
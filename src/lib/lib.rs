@@ -1,126 +1,1132 @@
-use anyhow::{anyhow, bail, Result};
-use either::Either;
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::time::Instant;
+
 use itertools::Itertools;
-use semver::{Comparator, Prerelease, Version};
+use regex::RegexBuilder;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
 
-use crate::crates::CratesIndex;
+use crate::advisory::AdvisoryDb;
+use crate::backend::IndexBackend;
+use crate::crates::Crate;
+use crate::deadline::Deadline;
+use crate::dump::Dump;
+use crate::error::{Error, Result};
+use crate::filter::CrateFilter;
+use crate::history::{History, CRATE, FEATURE};
+use crate::license::LicensePolicy;
+use crate::matching::{
+    abbreviation_score, contains_score, edit_distance, fuzzy_score, CaseSensitivity, MatchMode,
+};
+use crate::toolchain::{requires_newer_edition, supports};
 
+pub mod advisory;
+pub mod api;
+pub mod backend;
+pub mod bump;
+pub mod cache;
+pub mod config;
 pub mod crates;
+pub mod deadline;
+pub mod dump;
+pub mod error;
+pub mod filter;
+pub mod git;
+pub mod history;
+pub mod license;
+pub mod line;
+pub mod local;
+pub mod manifest;
+pub mod matching;
+pub mod net;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod persist;
+pub mod popularity;
+pub mod remote;
+pub mod sparse;
+pub mod toolchain;
 
-fn satisfied_versions(index: &CratesIndex, crate_name: &str, req: &str) -> Result<Vec<Version>> {
-    let crate_ = index
-        .crate_(crate_name)?
-        .ok_or_else(|| anyhow!("unable to find crate"))?;
-    let versions = crate_.detail()?;
-    Ok(versions
-        .iter()
-        .filter(|version| version.version.starts_with(req) && !version.yanked)
-        .map(|version| Version::parse(version.version.as_str()).unwrap()) // TODO error handling
-        .rev()
-        .collect())
+/// A completion candidate with a short description for shells able to display one.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+    pub value: String,
+    /// Text shown instead of the value, e.g. without the prefix the shell already typed.
+    pub display: Option<String>,
+    pub description: Option<String>,
+    /// How well the candidate matches, higher being better, for matchers that rank.
+    pub score: Option<i64>,
+    pub kind: Kind,
+    /// Whether the version, or every version of the crate, is yanked.
+    pub yanked: bool,
 }
 
-type Field = Either<u64, Prerelease>;
+/// What a candidate completes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Crate,
+    Version,
+    Feature,
+    Flag,
+    /// The value of a flag, e.g. a registry or a git branch.
+    Value,
+}
 
-#[derive(Debug, Copy, Clone)]
-enum FieldType {
-    Major,
-    Minor,
-    Patch,
-    Pre,
+impl Candidate {
+    /// Text to show for the candidate.
+    pub fn label(&self) -> &str {
+        self.display.as_deref().unwrap_or(&self.value)
+    }
 }
 
-fn first_unfilled_field(req: Option<&Comparator>) -> Option<FieldType> {
-    if let Some(req) = req {
-        if req.minor.is_none() {
-            return Some(FieldType::Minor);
-        }
-        if req.patch.is_none() {
-            return Some(FieldType::Patch);
-        }
-        if req.pre.is_empty() {
-            return Some(FieldType::Pre);
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Crate => "crate",
+            Kind::Version => "version",
+            Kind::Feature => "feature",
+            Kind::Flag => "flag",
+            Kind::Value => "value",
         }
-        None
-    } else {
-        Some(FieldType::Major)
     }
 }
 
-fn extract_field(version: &Version, field: FieldType) -> Field {
-    match field {
-        FieldType::Major => Field::Left(version.major),
-        FieldType::Minor => Field::Left(version.minor),
-        FieldType::Patch => Field::Left(version.patch),
-        FieldType::Pre => Field::Right(version.pre.clone()),
+/// Shorthand for the newest version that is neither yanked nor a prerelease.
+const LATEST: &str = "latest";
+
+/// Told about versions whose license the license policy couldn't look up.
+const LICENSE_UNKNOWN: &str = "license unknown";
+
+/// How much each signal counts when ranking crate names, summed into one score in place of the
+/// fixed order of matcher score, downloads and history.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Weights {
+    /// Per point the matcher scores a name with.
+    pub score: f64,
+    /// Per tenfold downloads, counted with `rank_by_downloads`.
+    pub downloads: f64,
+    /// Per point of frecency of the crate in the history.
+    pub history: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            score: 1.0,
+            downloads: 1.0,
+            history: 1.0,
+        }
     }
 }
 
-fn query_prefix(req: &Comparator) -> String {
-    let mut output = req.major.to_string();
-    if let Some(minor) = req.minor {
-        output.push('.');
-        output.push_str(&*minor.to_string());
+/// Which versions to complete.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// List prerelease versions even when the typed version has no prerelease part.
+    pub include_prerelease: bool,
+    /// List yanked versions, marked as such.
+    pub include_yanked: bool,
+    /// Leave out crates whose every version is yanked instead of marking them.
+    pub hide_yanked_crates: bool,
+    /// List every version instead of only the newest patch of each minor version.
+    pub all_versions: bool,
+    /// The toolchain to check the `rust-version` of crates against, if any.
+    pub rustc: Option<Version>,
+    /// Leave out versions too new for `rustc` instead of listing them last.
+    pub hide_incompatible: bool,
+    /// Leave out versions needing an edition newer than this one.
+    pub max_edition: Option<String>,
+    /// Advisories to mark affected versions with, if any.
+    pub advisory_db: Option<AdvisoryDb>,
+    /// Leave out versions affected by an advisory instead of marking them.
+    pub hide_vulnerable: bool,
+    /// Leave out crates and versions under licenses the policy doesn't allow.
+    pub license_policy: Option<LicensePolicy>,
+    /// List the most downloaded crates first.
+    pub rank_by_downloads: bool,
+    /// Imported crates.io metadata to rank and describe crates with offline, if any.
+    pub dump: Option<Dump>,
+    /// How typed text selects crate names.
+    pub match_mode: MatchMode,
+    /// Whether prefixes match crate names regardless of case; fuzzy matchers always ignore it.
+    pub case: CaseSensitivity,
+    /// Crates and features picked before, to list first.
+    pub history: Option<History>,
+    /// Crates to always hide or always show.
+    pub crate_filter: CrateFilter,
+    /// Prefixes of crates to list before all others, e.g. `mycorp-` for internal crates.
+    pub boost_prefixes: Vec<String>,
+    /// Rank crate names by a weighted sum of their signals instead of one signal after another.
+    pub weights: Option<Weights>,
+    /// Describe no more than this many crate names, plus one to tell whether there are more.
+    pub limit: Option<usize>,
+    /// Stop looking for crates once this passed, completing the ones found so far. Frontends can
+    /// abort a completion this way when it is no longer wanted, see [`Deadline::cancelled_by`].
+    pub deadline: Option<Deadline>,
+}
+
+impl Options {
+    pub(crate) fn expired(&self) -> bool {
+        self.deadline.as_ref().is_some_and(Deadline::expired)
+    }
+
+    fn supports(&self, version: &Crate) -> bool {
+        self.rustc
+            .as_ref()
+            .is_none_or(|rustc| supports(rustc, version.rust_version.as_deref()))
+    }
+
+    fn permits(&self, version: &Crate) -> bool {
+        self.crate_filter.allows(&version.name)
+            || self
+                .license_policy
+                .as_ref()
+                .is_none_or(|policy| policy.permits(version))
     }
-    if let Some(patch) = req.patch {
-        output.push('.');
-        output.push_str(&*patch.to_string());
+
+    /// Whether the license policy can't tell the license of a version, as neither the index nor
+    /// crates.io do.
+    fn license_unknown(&self, version: &Crate) -> bool {
+        self.license_policy
+            .as_ref()
+            .is_some_and(|policy| policy.license(version).is_none())
+    }
+
+    /// Ids of the advisories affecting a version.
+    fn advisories(&self, version: &Crate) -> Vec<String> {
+        match (&self.advisory_db, Version::parse(&version.version)) {
+            (Some(db), Ok(parsed)) => db.affecting(&version.name, &parsed),
+            _ => vec![],
+        }
+    }
+
+    /// What to tell about a version in its description, e.g. that it is yanked.
+    fn notes(&self, version: &Crate) -> Option<String> {
+        let mut notes = vec![];
+        if version.yanked {
+            notes.push("yanked".to_string());
+        }
+        let advisories = self.advisories(version);
+        if !advisories.is_empty() {
+            notes.push(format!("vulnerable: {}", advisories.join(", ")));
+        }
+        if !self.supports(version) {
+            if let Some(rust_version) = &version.rust_version {
+                notes.push(format!("requires rustc {}", rust_version));
+            }
+        }
+        if self.license_unknown(version) {
+            notes.push(LICENSE_UNKNOWN.to_string());
+        }
+        (!notes.is_empty()).then(|| notes.join("; "))
     }
-    if !req.pre.is_empty() {
-        output.push('-');
-        output.push_str(req.pre.as_str());
+}
+
+/// Versions of a crate, treating `-` and `_` in its name alike as crates.io does.
+pub(crate) fn versions(index: &dyn IndexBackend, crate_name: &str) -> Result<Option<Vec<Crate>>> {
+    if let Some(versions) = index.versions(crate_name)? {
+        return Ok(Some(versions));
     }
-    output
+    let normalized = normalize_name(crate_name);
+    match index
+        .crates_with_prefix(crate_name)?
+        .into_iter()
+        .find(|name| name != crate_name && normalize_name(name) == normalized)
+    {
+        Some(name) => Ok(index.versions(&name)?),
+        None => Ok(None),
+    }
+}
+
+fn satisfied_versions(
+    index: &dyn IndexBackend,
+    crate_name: &str,
+    req: &str,
+    options: &Options,
+) -> Result<Vec<(Version, Crate)>> {
+    let versions =
+        versions(index, crate_name)?.ok_or_else(|| Error::CrateNotFound(crate_name.to_string()))?;
+    // Prereleases are only wanted when asked for, or once the user typed one.
+    let include_prerelease = options.include_prerelease || req.contains('-');
+    // Patches are only worth listing one by one once the user picked the minor version.
+    let compact = !options.all_versions && req.matches('.').count() < 2;
+    Ok(versions
+        .into_iter()
+        .filter(|version| version.version.starts_with(req))
+        .filter(|version| options.include_yanked || !version.yanked)
+        .filter(|version| !options.hide_incompatible || options.supports(version))
+        .filter(|version| !options.hide_vulnerable || options.advisories(version).is_empty())
+        .filter(|version| options.permits(version))
+        .filter(|version| {
+            options.max_edition.as_deref().is_none_or(|max_edition| {
+                !requires_newer_edition(version.edition.as_deref(), max_edition)
+            })
+        })
+        .filter_map(|version| Some((Version::parse(&version.version).ok()?, version)))
+        .filter(|(version, _)| include_prerelease || version.pre.is_empty())
+        // Within a minor version, the newest patch the toolchain supports is the one to keep.
+        .sorted_by(|(a, crate_a), (b, crate_b)| {
+            let key_a = (a.major, a.minor, options.supports(crate_a), a);
+            key_a
+                .cmp(&(b.major, b.minor, options.supports(crate_b), b))
+                .reverse()
+        })
+        .dedup_by(|(a, _), (b, _)| compact && (a.major, a.minor) == (b.major, b.minor))
+        // Versions too new for the toolchain go last.
+        .sorted_by_key(|(_, crate_)| !options.supports(crate_))
+        .collect())
 }
 
+/// Complete the last comparator of a version requirement; suggestions have to agree with the
+/// `earlier` comparators before it.
 fn complete_version(
-    index: &CratesIndex,
+    index: &dyn IndexBackend,
     crate_name: &str,
+    earlier: &str,
     partial_ver: &str,
+    options: &Options,
+) -> Result<Vec<(String, Crate)>> {
+    // The comparator as typed, e.g. ` >=` of `>=1.2, <1.`, is kept in the suggestions.
+    let (operator, query_prefix) = partial_ver.split_at(
+        partial_ver
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(partial_ver.len()),
+    );
+    let op = operator.trim();
+    let earlier_req = match earlier.trim() {
+        "" => None,
+        earlier => Some(VersionReq::parse(earlier)?),
+    };
+
+    let versions = satisfied_versions(index, crate_name, query_prefix, options)?;
+    // A range bound is only useful if some version falls within the whole requirement with it.
+    let admitted = match op {
+        ">" | ">=" | "<" | "<=" => Some(satisfied_versions(
+            index,
+            crate_name,
+            "",
+            &Options {
+                all_versions: true,
+                ..options.clone()
+            },
+        )?),
+        _ => None,
+    };
+
+    Ok(versions
+        .into_iter()
+        .filter(|(version, _)| match &admitted {
+            Some(admitted) => {
+                let req = match earlier.trim() {
+                    "" => format!("{}{}", op, version),
+                    earlier => format!("{}, {}{}", earlier, op, version),
+                };
+                VersionReq::parse(&req)
+                    .is_ok_and(|req| admitted.iter().any(|(other, _)| req.matches(other)))
+            }
+            None => earlier_req.as_ref().is_none_or(|req| req.matches(version)),
+        })
+        .filter_map(|(version, crate_)| {
+            format!("{}{}", operator, version)
+                .strip_prefix(partial_ver)
+                .map(|s| (s.to_string(), crate_))
+        })
+        .collect())
+}
+
+/// Upper bounds worth adding to a requirement lacking one, e.g. `<2.0.0` and `<1.7` after
+/// `>=1.4,`: the semver compatible bound of the oldest admitted version, then one bound per minor
+/// version between the two, newest first.
+fn upper_bounds(
+    index: &dyn IndexBackend,
+    crate_name: &str,
+    earlier: &str,
+    options: &Options,
 ) -> Result<Vec<String>> {
-    let query_prefix = partial_ver.trim().trim_start_matches(&['>', '<', '=', '~', '^'][..]).trim_start_matches('=');
+    let req = VersionReq::parse(earlier.trim())?;
+    let admitted: Vec<Version> = satisfied_versions(
+        index,
+        crate_name,
+        "",
+        &Options {
+            all_versions: true,
+            ..options.clone()
+        },
+    )?
+    .into_iter()
+    .map(|(version, _)| version)
+    .filter(|version| req.matches(version))
+    .sorted_by(|a, b| b.cmp(a))
+    .collect();
+    let oldest = match admitted.last() {
+        Some(oldest) => oldest,
+        None => return Ok(vec![]),
+    };
+    let (compatible, bound) = match oldest.major {
+        0 => (format!("<0.{}", oldest.minor + 1), (0, oldest.minor + 1)),
+        major => (format!("<{}.0.0", major + 1), (major + 1, 0)),
+    };
+    let minors = admitted
+        .iter()
+        .filter(|version| (version.major, version.minor) > (oldest.major, oldest.minor))
+        .filter(|version| (version.major, version.minor) < bound)
+        .map(|version| format!("<{}.{}", version.major, version.minor))
+        .dedup();
+    Ok(std::iter::once(compatible).chain(minors).collect())
+}
 
-    let versions = satisfied_versions(index, crate_name, &query_prefix)?;
+/// How many suggestions to make for a misspelled crate name.
+const SUGGESTIONS: usize = 10;
 
-    Ok(versions.into_iter().filter_map(|version|version.to_string().strip_prefix(&partial_ver).map(|s|s.to_string())).collect())
+/// Scores a crate name against what was typed, `None` meaning no match.
+type Scorer<'a> = Box<dyn Fn(&str) -> Option<i64> + 'a>;
+
+/// Crate names matching a partial name along with their score, best first for ranking matchers.
+fn complete_crate_name(
+    index: &dyn IndexBackend,
+    partial_name: &str,
+    options: &Options,
+) -> Result<Vec<(String, Option<i64>)>> {
+    let names: Vec<_> = match options.match_mode {
+        MatchMode::Prefix => {
+            // Index entries are stored under lowercase names, so a lowercase prefix finds
+            // crates whatever the case of their name.
+            let prefix = match options.case.ignores_case(partial_name) {
+                true => partial_name.to_lowercase(),
+                false => partial_name.to_string(),
+            };
+            // The name typed in full first, then the shortest, as the limit keeps the first few.
+            let typed = normalize_name(partial_name);
+            index
+                .crates_with_prefix_until(&prefix, options.deadline.as_ref())?
+                .into_iter()
+                .sorted_by_key(|name| (normalize_name(name) != typed, name.len(), name.clone()))
+                .map(|name| (name, None))
+                .collect()
+        }
+        MatchMode::Fuzzy | MatchMode::Abbreviation | MatchMode::Contains => {
+            // Fuzzy and abbreviation scores always ignore case, and index entries are stored under
+            // lowercase names, so the anchor is lowercase too.
+            let first = partial_name
+                .chars()
+                .next()
+                .map(|first| first.to_lowercase().collect())
+                .unwrap_or_default();
+            let ignore_case = options.case.ignores_case(partial_name);
+            // Anchor on the first character where possible so only part of the index has to be
+            // walked.
+            let (source, matcher): (_, Scorer) = match options.match_mode {
+                MatchMode::Fuzzy => (first, Box::new(|name| fuzzy_score(partial_name, name))),
+                MatchMode::Abbreviation => (
+                    first,
+                    Box::new(|name| abbreviation_score(partial_name, name)),
+                ),
+                _ => (
+                    String::new(),
+                    Box::new(move |name| contains_score(partial_name, name, ignore_case)),
+                ),
+            };
+            index
+                .crates_with_prefix_until(&source, options.deadline.as_ref())?
+                .into_iter()
+                .filter_map(|name| {
+                    let score = matcher(&name)?;
+                    Some((name, Some(score)))
+                })
+                .sorted_by(|(a, a_score), (b, b_score)| {
+                    b_score.cmp(a_score).then(a.len().cmp(&b.len()))
+                })
+                .collect()
+        }
+        MatchMode::Regex => {
+            let pattern = RegexBuilder::new(partial_name)
+                .case_insensitive(options.case.ignores_case(partial_name))
+                .build()?;
+            // A pattern can't be misspelled like a name, so there is nothing to suggest either.
+            return Ok(index
+                .crates_with_prefix_until("", options.deadline.as_ref())?
+                .into_iter()
+                .filter(|name| pattern.is_match(name))
+                .map(|name| (name, None))
+                .collect());
+        }
+    };
+    if !names.is_empty() {
+        return Ok(names);
+    }
+    Ok(similar_crate_names(index, partial_name)?
+        .into_iter()
+        .map(|name| (name, None))
+        .collect())
 }
 
-fn complete_crate_name(index: &CratesIndex, partial_name: &str) -> Result<Vec<String>> {
+/// Crate names a few typos away from a name no crate starts with, nearest first.
+///
+/// Only names with the same first character are considered, as typos rarely hit it and
+/// walking the whole index would be slow.
+fn similar_crate_names(index: &dyn IndexBackend, name: &str) -> Result<Vec<String>> {
+    let name = normalize_name(name);
+    let first = match name.chars().next() {
+        Some(first) => first.to_string(),
+        None => return Ok(vec![]),
+    };
+    let max_distance = (name.chars().count() / 3).max(1);
     Ok(index
-        .crates_with_prefix(partial_name)?
+        .crates_with_prefix(&first)?
         .into_iter()
-        .map(|crate_| crate_.name)
+        .map(|candidate| (edit_distance(&name, &normalize_name(&candidate)), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .sorted()
+        .take(SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
         .collect())
 }
 
-pub fn complete_crate(index: &CratesIndex, partial_command: &str) -> Result<Vec<String>> {
+/// The newest version that is not yanked, preferring stable releases over prereleases.
+fn latest(versions: &[Crate]) -> Option<&Crate> {
+    versions
+        .iter()
+        .filter(|version| !version.yanked)
+        .filter_map(|version| Some((Version::parse(&version.version).ok()?, version)))
+        .max_by(|(a, _), (b, _)| (a.pre.is_empty(), a).cmp(&(b.pre.is_empty(), b)))
+        .map(|(_, version)| version)
+}
+
+pub(crate) fn latest_version(index: &dyn IndexBackend, crate_name: &str) -> Option<String> {
+    latest(&versions(index, crate_name).ok()??).map(|latest| latest.version.clone())
+}
+
+/// Complete a crate name, or a version after `@`, with default options.
+pub fn complete_crate(index: &dyn IndexBackend, partial_command: &str) -> Result<Vec<Candidate>> {
+    complete_crate_described(index, partial_command, &Options::default())
+}
+
+/// Like [`complete_crate`], describing crate names with their latest version.
+pub fn complete_crate_described(
+    index: &dyn IndexBackend,
+    partial_command: &str,
+    options: &Options,
+) -> Result<Vec<Candidate>> {
     if let Some((name, vers)) = partial_command.split_once("@") {
-        let last_ver = vers.rsplit(',').next().unwrap_or_default();
-        Ok(complete_version(index, name, last_ver)?
+        if options.crate_filter.denies(name) {
+            return Ok(vec![]);
+        }
+        if vers == LATEST {
+            // Expand the shorthand to the version it stands for.
+            return Ok(latest_version(index, name)
+                .map(|version| Candidate {
+                    value: format!("{}@{}", name, version),
+                    display: Some(version),
+                    description: Some(LATEST.to_string()),
+                    score: None,
+                    kind: Kind::Version,
+                    yanked: false,
+                })
+                .into_iter()
+                .collect());
+        }
+        let shorthand = (!vers.is_empty() && LATEST.starts_with(vers)).then(|| Candidate {
+            value: format!("{}@{}", name, LATEST),
+            display: Some(LATEST.to_string()),
+            description: latest_version(index, name),
+            score: None,
+            kind: Kind::Version,
+            yanked: false,
+        });
+        let (earlier, last_ver) = vers.rsplit_once(',').unwrap_or(("", vers));
+        if !earlier.trim().is_empty() && last_ver.trim().is_empty() {
+            // Right after a comma, the missing part is usually an upper bound.
+            let separator = if last_ver.is_empty() { " " } else { "" };
+            return Ok(upper_bounds(index, name, earlier, options)?
+                .into_iter()
+                .map(|bound| Candidate {
+                    value: format!("{}{}{}", partial_command, separator, bound),
+                    display: Some(bound),
+                    description: Some("upper bound".to_string()),
+                    score: None,
+                    kind: Kind::Version,
+                    yanked: false,
+                })
+                .collect());
+        }
+        Ok(shorthand
             .into_iter()
-            .map(|part| format!("{}{}", partial_command, part))
+            .chain(
+                complete_version(index, name, earlier, last_ver, options)?
+                    .into_iter()
+                    .map(|(part, crate_)| Candidate {
+                        value: format!("{}{}", partial_command, part),
+                        display: Some(format!("{}{}", vers, part)),
+                        description: options.notes(&crate_),
+                        score: None,
+                        kind: Kind::Version,
+                        yanked: crate_.yanked,
+                    }),
+            )
             .collect())
     } else {
-        Ok(complete_crate_name(index, partial_command)?)
+        Ok(complete_crate_described_iter(index, partial_command, options)?.collect())
     }
 }
 
-pub fn complete_feature(
-    index: &CratesIndex,
+/// Like [`complete_crate_iter`], leaving out the crates whose versions can't be read, and failing
+/// only if completing fails as a whole.
+pub fn complete_crate_described_iter<'a>(
+    index: &'a dyn IndexBackend,
+    partial_command: &str,
+    options: &'a Options,
+) -> Result<Box<dyn Iterator<Item = Candidate> + 'a>> {
+    Ok(Box::new(
+        crate_candidates(index, partial_command, options)?.filter_map(Result::ok),
+    ))
+}
+
+/// Like [`complete_crate_described`], yielding candidates one by one as they are found so they
+/// can be shown before all are, and failing only the crates whose versions can't be read.
+///
+/// Crate names are described lazily, so dropping the iterator, e.g. once another key was typed,
+/// abandons the rest of the work.
+pub fn complete_crate_iter<'a>(
+    index: &'a dyn IndexBackend,
+    partial_command: &str,
+    options: &'a Options,
+) -> impl Iterator<Item = Result<Candidate>> + 'a {
+    let candidates: Box<dyn Iterator<Item = Result<Candidate>> + 'a> =
+        match crate_candidates(index, partial_command, options) {
+            Ok(candidates) => candidates,
+            Err(err) => Box::new(iter::once(Err(err))),
+        };
+    candidates
+}
+
+/// Candidates of [`complete_crate_iter`], or the error failing them all.
+fn crate_candidates<'a>(
+    index: &'a dyn IndexBackend,
+    partial_command: &str,
+    options: &'a Options,
+) -> Result<Box<dyn Iterator<Item = Result<Candidate>> + 'a>> {
+    if partial_command.contains('@') {
+        let candidates = complete_crate_described(index, partial_command, options)?;
+        return Ok(Box::new(candidates.into_iter().map(Ok)));
+    }
+    Ok(Box::new(
+        ranked_crate_names(index, partial_command, options)?
+            .into_iter()
+            .take_while(move |_| !options.expired())
+            .filter_map(move |(name, score)| match versions(index, &name) {
+                Ok(versions) => {
+                    let registry = index.registry(&name);
+                    let versions = versions.unwrap_or_default();
+                    describe_crate(name, score, versions, registry, options).map(Ok)
+                }
+                Err(err) => Some(Err(err)),
+            })
+            // Looking up the versions of every crate for its description is the slow part.
+            .take(options.limit.map_or(usize::MAX, |limit| limit + 1)),
+    ))
+}
+
+/// Crate names matching a partial name, ranked by the options but not yet described.
+pub(crate) fn ranked_crate_names(
+    index: &dyn IndexBackend,
+    partial_command: &str,
+    options: &Options,
+) -> Result<Vec<(String, Option<i64>)>> {
+    let start = Instant::now();
+    let mut names = complete_crate_name(index, partial_command, options)?;
+    debug!(matched = names.len(), elapsed = ?start.elapsed(), "matched crate names");
+    names.retain(|(name, _)| !options.crate_filter.denies(name));
+    if let Some(weights) = &options.weights {
+        let downloads = match options.rank_by_downloads {
+            true => popularity::downloads(partial_command, options.dump.as_ref()),
+            false => HashMap::new(),
+        };
+        let rank = |(name, score): &(String, Option<i64>)| {
+            let downloads = downloads
+                .get(name)
+                .map_or(0.0, |&count| (count as f64).log10());
+            let frecency = options
+                .history
+                .as_ref()
+                .map_or(0.0, |history| history.frecency(CRATE, name));
+            weights.score * score.unwrap_or(0) as f64
+                + weights.downloads * downloads.max(0.0)
+                + weights.history * frecency
+        };
+        // Stable, so names ranked the same keep the order of the matcher.
+        names.sort_by(|a, b| rank(b).total_cmp(&rank(a)));
+    } else if options.rank_by_downloads {
+        let downloads = popularity::downloads(partial_command, options.dump.as_ref());
+        // Stable, so equally popular crates keep the order of the matcher, and downloads only
+        // break ties between matches the matcher scored the same.
+        names.sort_by_key(|(name, score)| {
+            (
+                std::cmp::Reverse(*score),
+                std::cmp::Reverse(downloads.get(name).copied().unwrap_or(0)),
+            )
+        });
+    }
+    if let (Some(history), None) = (&options.history, &options.weights) {
+        history.boost(CRATE, |(name, _)| name.clone(), &mut names);
+    }
+    if !options.boost_prefixes.is_empty() {
+        names.sort_by_key(|(name, _)| {
+            let name = normalize_name(name);
+            !options
+                .boost_prefixes
+                .iter()
+                .any(|prefix| name.starts_with(&normalize_name(prefix)))
+        });
+    }
+    Ok(names)
+}
+
+/// A crate name described by its latest version and summary, and by the `registry` it is from
+/// when merging several, or `None` if the options hide it.
+pub(crate) fn describe_crate(
+    name: String,
+    score: Option<i64>,
+    versions: Vec<Crate>,
+    registry: Option<String>,
+    options: &Options,
+) -> Option<Candidate> {
+    let yanked = !versions.is_empty() && versions.iter().all(|version| version.yanked);
+    let description = match latest(&versions) {
+        Some(latest) if !options.permits(latest) => return None,
+        Some(latest) if options.license_unknown(latest) => {
+            Some(format!("latest {}; {}", latest.version, LICENSE_UNKNOWN))
+        }
+        Some(latest) => Some(format!("latest {}", latest.version)),
+        None if versions.is_empty() => None,
+        // Steer users away from crates that can no longer be depended on.
+        None if options.hide_yanked_crates => return None,
+        None => Some("all versions yanked".to_string()),
+    };
+    let summary = options
+        .dump
+        .as_ref()
+        .and_then(|dump| dump.description(&name).ok().flatten());
+    let description = match (description, summary) {
+        (Some(description), Some(summary)) => Some(format!("{} - {}", description, summary)),
+        (description, summary) => description.or(summary),
+    };
+    let description = match (registry, description) {
+        (Some(registry), Some(description)) => Some(format!("{}: {}", registry, description)),
+        (registry, description) => registry.or(description),
+    };
+    Some(Candidate {
+        value: name,
+        description,
+        score,
+        display: None,
+        kind: Kind::Crate,
+        yanked,
+    })
+}
+
+/// Resolve a version requirement the way cargo would: the highest satisfying version, preferring
+/// ones that are not yanked.
+///
+/// Requirements ignore build metadata, so versions only differing in it are told apart by the
+/// metadata typed after a `+`, if any.
+fn resolve_version(index: &dyn IndexBackend, crate_name: &str, req: &str) -> Result<Crate> {
+    let build = req.rsplit_once('+').map(|(_, build)| build.trim());
+    let req = match req.trim() {
+        // Requirements without a prerelease never match one, so this is the newest release.
+        "" | LATEST => VersionReq::STAR,
+        req => VersionReq::parse(req)?,
+    };
+    versions(index, crate_name)?
+        .ok_or_else(|| Error::CrateNotFound(crate_name.to_string()))?
+        .into_iter()
+        .filter_map(|crate_| Some((Version::parse(&crate_.version).ok()?, crate_)))
+        .filter(|(version, _)| req.matches(version))
+        .filter(|(version, _)| build.is_none_or(|build| version.build.as_str() == build))
+        .max_by(|(a, crate_a), (b, crate_b)| (!crate_a.yanked, a).cmp(&(!crate_b.yanked, b)))
+        .map(|(_, crate_)| crate_)
+        .ok_or_else(|| Error::VersionNotFound {
+            name: crate_name.to_string(),
+            req: req.to_string(),
+        })
+}
+
+fn features(
+    index: &dyn IndexBackend,
     crate_name: &str,
     version: &str,
-) -> Result<Vec<String>> {
-    let crate_ = index
-        .crate_(crate_name)?
-        .ok_or_else(|| anyhow!("missing crate"))?;
-    Ok(crate_
-        .detail()?
+) -> Result<HashMap<String, Vec<String>>> {
+    Ok(resolve_version(index, crate_name, version)?.all_features())
+}
+
+/// Normalize a crate name for comparison, as crates.io treats `-` and `_` alike.
+fn normalize_name(name: &str) -> String {
+    name.replace('-', "_").to_lowercase()
+}
+
+/// Complete the next package spec of `cargo add a b <partial>`, leaving out crates named by the
+/// already entered `specs`.
+pub fn complete_next_crate(
+    index: &dyn IndexBackend,
+    specs: &[&str],
+    partial: &str,
+    options: &Options,
+) -> Result<Vec<Candidate>> {
+    let chosen: Vec<String> = specs
+        .iter()
+        .map(|spec| normalize_name(spec.split_once('@').map_or(*spec, |(name, _)| name)))
+        .collect();
+    // Chosen crates are left out afterwards, so make room for them.
+    let options = Options {
+        limit: options.limit.map(|limit| limit + chosen.len()),
+        ..options.clone()
+    };
+    Ok(complete_crate_described(index, partial, &options)?
         .into_iter()
-        .filter(|ver| ver.version.starts_with(version))
-        .last()
-        .ok_or_else(|| anyhow!("missing version"))?
-        .features
-        .keys()
-        .cloned()
+        .filter(|candidate| {
+            partial.contains('@') || !chosen.contains(&normalize_name(&candidate.value))
+        })
+        .collect())
+}
+
+/// Complete the features of a crate version starting with `partial`.
+pub fn complete_feature(
+    index: &dyn IndexBackend,
+    crate_name: &str,
+    version: &str,
+    partial: &str,
+) -> Result<Vec<Candidate>> {
+    complete_feature_described(index, crate_name, version, partial)
+}
+
+/// Features enabled by the `default` feature, directly or through other features.
+fn default_features(features: &HashMap<String, Vec<String>>) -> HashSet<&str> {
+    let mut enabled = HashSet::new();
+    let mut pending = vec!["default"];
+    while let Some(feature) = pending.pop() {
+        for enable in features.get(feature).into_iter().flatten() {
+            // `dep:` and `crate/feature` entries enable no feature of this crate.
+            if features.contains_key(enable.as_str()) && enabled.insert(enable.as_str()) {
+                pending.push(enable);
+            }
+        }
+    }
+    enabled
+}
+
+/// Complete `dep/feature`, i.e. a feature of the dependency `dep` of a crate version, resolving
+/// the dependency with the requirement the crate places on it.
+///
+/// `separator` is `/`, or `?/` for the weak form only enabling the feature if `dep` is enabled.
+fn complete_dependency_feature(
+    index: &dyn IndexBackend,
+    crate_name: &str,
+    version: &str,
+    dep: &str,
+    separator: &str,
+    partial: &str,
+) -> Result<Vec<Candidate>> {
+    let version = resolve_version(index, crate_name, version)?;
+    let dependency = version
+        .deps
+        .iter()
+        .find(|dependency| dependency.name == dep)
+        .ok_or_else(|| Error::DependencyNotFound {
+            name: crate_name.to_string(),
+            dependency: dep.to_string(),
+        })?;
+    let package = dependency.package.as_deref().unwrap_or(&dependency.name);
+    Ok(
+        complete_feature_described(index, package, &dependency.req, partial)?
+            .into_iter()
+            .map(|candidate| Candidate {
+                value: format!("{}{}{}", dep, separator, candidate.value),
+                ..candidate
+            })
+            .collect(),
+    )
+}
+
+/// Like [`complete_feature_described`], listing the features picked before first.
+pub fn complete_feature_ranked(
+    index: &dyn IndexBackend,
+    crate_name: &str,
+    version: &str,
+    partial: &str,
+    options: &Options,
+) -> Result<Vec<Candidate>> {
+    let mut candidates = complete_feature_described(index, crate_name, version, partial)?;
+    if let Some(history) = &options.history {
+        history.boost(
+            FEATURE,
+            |candidate| format!("{}:{}", crate_name, candidate.value),
+            &mut candidates,
+        );
+    }
+    Ok(candidates)
+}
+
+/// Like [`complete_feature`], describing features with what they enable and whether they are
+/// enabled by default.
+///
+/// After a `/` or `?/`, features of the named dependency are completed instead.
+pub fn complete_feature_described(
+    index: &dyn IndexBackend,
+    crate_name: &str,
+    version: &str,
+    partial: &str,
+) -> Result<Vec<Candidate>> {
+    if let Some((dep, partial)) = partial.split_once('/') {
+        return match dep.strip_suffix('?') {
+            Some(dep) => {
+                complete_dependency_feature(index, crate_name, version, dep, "?/", partial)
+            }
+            None => complete_dependency_feature(index, crate_name, version, dep, "/", partial),
+        };
+    }
+    let features = features(index, crate_name, version)?;
+    let defaults = default_features(&features);
+    Ok(features
+        .iter()
+        .filter(|(feature, _)| feature.starts_with(partial))
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(feature, enables)| {
+            let mut description = vec![];
+            if defaults.contains(feature.as_str()) {
+                description.push("default".to_string());
+            }
+            if *enables == [format!("dep:{}", feature)] {
+                description.push("optional dependency".to_string());
+            } else if !enables.is_empty() {
+                description.push(format!("enables: {}", enables.join(", ")));
+            }
+            Candidate {
+                value: feature.clone(),
+                display: None,
+                description: (!description.is_empty()).then(|| description.join("; ")),
+                score: None,
+                kind: Kind::Feature,
+                yanked: false,
+            }
+        })
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MemoryIndex, MultiIndex};
+
+    fn version(name: &str, version: &str, yanked: bool) -> Crate {
+        Crate {
+            name: name.to_string(),
+            version: version.to_string(),
+            deps: Default::default(),
+            features: Default::default(),
+            yanked,
+            rust_version: None,
+            edition: None,
+            license: None,
+        }
+    }
+
+    /// `foo` with a few minor versions, a yanked patch and a prerelease.
+    fn index() -> MemoryIndex {
+        let mut index = MemoryIndex::new();
+        for (vers, yanked) in [
+            ("1.0.0", false),
+            ("1.0.1", false),
+            ("1.1.0", false),
+            ("1.1.1", true),
+            ("1.2.0-beta.1", false),
+            ("2.0.0", false),
+        ] {
+            index.insert(version("foo", vers, yanked));
+        }
+        index
+    }
+
+    fn values(partial: &str, options: &Options) -> Vec<String> {
+        complete_crate_described(&index(), partial, options)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.value)
+            .collect()
+    }
+
+    #[test]
+    fn newest_patch_of_each_minor_version() {
+        let options = Options::default();
+        assert_eq!(values("foo@1", &options), ["foo@1.1.0", "foo@1.0.1"]);
+        assert_eq!(
+            values("foo@", &options),
+            ["foo@2.0.0", "foo@1.1.0", "foo@1.0.1"]
+        );
+        // Patches are listed one by one once the minor version is typed.
+        assert_eq!(values("foo@1.0.", &options), ["foo@1.0.1", "foo@1.0.0"]);
+    }
+
+    #[test]
+    fn all_yanked_and_prerelease_versions_on_request() {
+        let options = Options {
+            all_versions: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            values("foo@1", &options),
+            ["foo@1.1.0", "foo@1.0.1", "foo@1.0.0"]
+        );
+        let options = Options {
+            include_yanked: true,
+            include_prerelease: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            values("foo@1", &options),
+            ["foo@1.2.0-beta.1", "foo@1.1.1", "foo@1.0.1"]
+        );
+        let yanked = complete_crate_described(&index(), "foo@1.1.1", &options).unwrap();
+        assert!(yanked[0].yanked);
+        assert_eq!(yanked[0].description.as_deref(), Some("yanked"));
+    }
+
+    #[test]
+    fn operators_are_kept() {
+        let options = Options::default();
+        assert_eq!(values("foo@^1.0", &options), ["foo@^1.0.1"]);
+        assert_eq!(values("foo@>=1.", &options), ["foo@>=1.1.0", "foo@>=1.0.1"]);
+        assert_eq!(
+            values("foo@>=1.0, <1.", &options),
+            ["foo@>=1.0, <1.1.0", "foo@>=1.0, <1.0.1"]
+        );
+        // No version is both below 1.0.0 and above any 1.x.
+        assert!(values("foo@<1.0.0, >1", &options).is_empty());
+    }
+
+    #[test]
+    fn upper_bounds_after_a_comma() {
+        let options = Options::default();
+        assert_eq!(
+            values("foo@>=1.0,", &options),
+            ["foo@>=1.0, <2.0.0", "foo@>=1.0, <1.1"]
+        );
+        assert_eq!(
+            upper_bounds(&index(), "foo", ">=1.1", &options).unwrap(),
+            ["<2.0.0"]
+        );
+    }
+
+    #[test]
+    fn fuzzy_anchor_ignores_case() {
+        let mut index = index();
+        index.insert(version("serde", "1.0.0", false));
+        for match_mode in [MatchMode::Fuzzy, MatchMode::Abbreviation] {
+            let options = Options {
+                match_mode,
+                ..Options::default()
+            };
+            let names = complete_crate_name(&index, "Se", &options).unwrap();
+            assert_eq!(names[0].0, "serde", "{:?}", match_mode);
+        }
+        let options = Options {
+            match_mode: MatchMode::Fuzzy,
+            ..Options::default()
+        };
+        let names = complete_crate_name(&index, "Sr", &options).unwrap();
+        assert_eq!(names[0].0, "serde");
+    }
+
+    #[test]
+    fn prefix_matches_shortest_first() {
+        let mut index = index();
+        for name in ["ab-cd", "abc", "ab"] {
+            index.insert(version(name, "1.0.0", false));
+        }
+        let names: Vec<_> = complete_crate_name(&index, "ab", &Options::default())
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["ab", "abc", "ab-cd"]);
+    }
+
+    #[test]
+    fn weighted_ranking() {
+        let mut index = index();
+        for name in ["serde", "sea-orm-serde"] {
+            index.insert(version(name, "1.0.0", false));
+        }
+        let ranked = |weights| {
+            let options = Options {
+                match_mode: MatchMode::Fuzzy,
+                weights,
+                ..Options::default()
+            };
+            let names = ranked_crate_names(&index, "serde", &options).unwrap();
+            names.into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+        assert_eq!(ranked(None), ["serde", "sea-orm-serde"]);
+        assert_eq!(ranked(Some(Weights::default())), ["serde", "sea-orm-serde"]);
+        let weights = Weights {
+            score: -1.0,
+            ..Weights::default()
+        };
+        assert_eq!(ranked(Some(weights)), ["sea-orm-serde", "serde"]);
+    }
+
+    #[test]
+    fn registry_of_merged_crates() {
+        let mut mirror = MemoryIndex::new();
+        mirror.insert(version("bar", "1.0.0", false));
+        let index = MultiIndex::new(vec![
+            ("index.crates.io".to_string(), Box::new(index())),
+            ("mycorp.example".to_string(), Box::new(mirror)),
+        ]);
+        let described = |partial| {
+            let candidates = complete_crate_described(&index, partial, &Options::default());
+            candidates.unwrap()[0].description.clone().unwrap()
+        };
+        assert_eq!(described("fo"), "index.crates.io: latest 2.0.0");
+        assert_eq!(described("ba"), "mycorp.example: latest 1.0.0");
+    }
+
+    #[test]
+    fn suggestions_ignore_case() {
+        let mut index = index();
+        index.insert(version("serde", "1.0.0", false));
+        assert_eq!(similar_crate_names(&index, "Sedre").unwrap(), ["serde"]);
+    }
+
+    #[test]
+    fn latest_shorthand() {
+        let options = Options::default();
+        let latest = complete_crate_described(&index(), "foo@latest", &options).unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].value, "foo@2.0.0");
+        assert_eq!(latest[0].description.as_deref(), Some(LATEST));
+        let shorthand = complete_crate_described(&index(), "foo@la", &options).unwrap();
+        assert_eq!(shorthand[0].value, "foo@latest");
+        assert_eq!(shorthand[0].description.as_deref(), Some("2.0.0"));
+    }
+
+    #[test]
+    fn resolve_like_cargo() {
+        let mut index = index();
+        index.insert(version("foo", "3.0.0+a", false));
+        index.insert(version("foo", "3.0.0+b", false));
+        let resolve = |req| resolve_version(&index, "foo", req).map(|crate_| crate_.version);
+        assert_eq!(resolve("1").unwrap(), "1.1.0");
+        // A yanked version is only picked if nothing else matches.
+        assert_eq!(resolve("=1.1.1").unwrap(), "1.1.1");
+        assert_eq!(resolve("^2").unwrap(), "2.0.0");
+        assert_eq!(resolve("3.0.0+a").unwrap(), "3.0.0+a");
+        assert!(matches!(resolve("4"), Err(Error::VersionNotFound { .. })));
+        assert!(matches!(
+            resolve_version(&index, "bar", "1"),
+            Err(Error::CrateNotFound(_))
+        ));
+    }
+}
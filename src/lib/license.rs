@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::crates::Crate;
+use crate::{api, cache};
+
+/// License of each version of a crate, by version.
+type Licenses = HashMap<String, String>;
+
+/// How long the licenses looked up on crates.io are kept, as new versions come with their own.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Licenses a user accepts dependencies under, e.g. from `--license-allow "MIT OR Apache-2.0"`.
+#[derive(Debug, Clone, Default)]
+pub struct LicensePolicy {
+    allowed: Vec<String>,
+    /// Licenses of each version of a crate, by crate name, as published on crates.io.
+    published: Arc<Mutex<HashMap<String, Arc<Licenses>>>>,
+}
+
+/// Split an SPDX expression into identifiers, operators and parentheses.
+fn tokens(expr: &str) -> Vec<String> {
+    expr.replace('(', " ( ")
+        .replace(')', " ) ")
+        // `MIT/Apache-2.0` is the legacy spelling of `MIT OR Apache-2.0`.
+        .replace('/', " OR ")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+impl LicensePolicy {
+    /// Accept every license named in `allow`, whatever operators join them.
+    pub fn new(allow: &str) -> Self {
+        Self {
+            allowed: tokens(allow)
+                .into_iter()
+                .filter(|token| !["AND", "OR", "WITH", "(", ")"].contains(&token.as_str()))
+                .collect(),
+            published: Default::default(),
+        }
+    }
+
+    /// Licenses of the versions of a crate on crates.io, for indexes that don't record them.
+    ///
+    /// Cached on disk for a day, so listing many crates only asks crates.io about each once. A
+    /// failed lookup is only remembered until the process exits.
+    fn published(&self, crate_name: &str) -> Arc<Licenses> {
+        let mut published = self.published.lock().unwrap();
+        published
+            .entry(crate_name.to_string())
+            .or_insert_with(|| {
+                let cache_name = format!("licenses/{}", cache::hashed(crate_name));
+                let cached = cache::read(&cache_name, CACHE_TTL)
+                    .and_then(|content| serde_json::from_str(&content).ok());
+                if let Some(licenses) = cached {
+                    return Arc::new(licenses);
+                }
+                let versions = match api::versions(crate_name) {
+                    Ok(versions) => versions.unwrap_or_default(),
+                    Err(_) => return Arc::default(),
+                };
+                let licenses: Licenses = versions
+                    .into_iter()
+                    .filter_map(|version| Some((version.version, version.license?)))
+                    .collect();
+                if let Ok(content) = serde_json::to_string(&licenses) {
+                    cache::write(&cache_name, &content);
+                }
+                Arc::new(licenses)
+            })
+            .clone()
+    }
+
+    /// The license of a crate version, looking it up on crates.io if the index lacks it.
+    pub fn license(&self, version: &Crate) -> Option<String> {
+        version
+            .license
+            .clone()
+            .or_else(|| self.published(&version.name).get(&version.version).cloned())
+    }
+
+    /// Whether a crate version may be used, giving crates of unknown license the benefit of the
+    /// doubt; completions mark them instead.
+    pub fn permits(&self, version: &Crate) -> bool {
+        self.license(version)
+            .is_none_or(|license| self.allows(&license))
+    }
+
+    /// Whether a crate licensed under the SPDX expression `license` may be used.
+    pub fn allows(&self, license: &str) -> bool {
+        let tokens = tokens(license);
+        let mut pos = 0;
+        self.or(&tokens, &mut pos) && pos == tokens.len()
+    }
+
+    fn or(&self, tokens: &[String], pos: &mut usize) -> bool {
+        let mut allowed = self.and(tokens, pos);
+        while tokens.get(*pos).map(String::as_str) == Some("OR") {
+            *pos += 1;
+            // Evaluate the right side anyway to consume its tokens.
+            allowed |= self.and(tokens, pos);
+        }
+        allowed
+    }
+
+    fn and(&self, tokens: &[String], pos: &mut usize) -> bool {
+        let mut allowed = self.term(tokens, pos);
+        while tokens.get(*pos).map(String::as_str) == Some("AND") {
+            *pos += 1;
+            allowed &= self.term(tokens, pos);
+        }
+        allowed
+    }
+
+    fn term(&self, tokens: &[String], pos: &mut usize) -> bool {
+        let token = match tokens.get(*pos) {
+            Some(token) => token,
+            None => return false,
+        };
+        *pos += 1;
+        if token == "(" {
+            let allowed = self.or(tokens, pos);
+            if tokens.get(*pos).map(String::as_str) != Some(")") {
+                return false;
+            }
+            *pos += 1;
+            return allowed;
+        }
+        // An exception only ever loosens the license it is attached to.
+        if tokens.get(*pos).map(String::as_str) == Some("WITH") {
+            *pos += 2;
+        }
+        self.allowed
+            .iter()
+            .any(|allowed| allowed == token.trim_end_matches('+'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_expressions() {
+        let policy = LicensePolicy::new("MIT OR Apache-2.0");
+        for (license, allowed) in [
+            ("MIT", true),
+            ("GPL-3.0", false),
+            ("MIT OR GPL-3.0", true),
+            ("GPL-3.0 OR MIT", true),
+            ("MIT AND GPL-3.0", false),
+            ("MIT AND Apache-2.0", true),
+            // AND binds tighter than OR.
+            ("GPL-3.0 OR MIT AND Apache-2.0", true),
+            ("MIT AND GPL-3.0 OR GPL-2.0", false),
+            ("GPL-3.0 OR MIT AND GPL-2.0", false),
+            ("(GPL-3.0 OR MIT) AND Apache-2.0", true),
+            ("(GPL-3.0 OR MIT) AND GPL-2.0", false),
+            ("MIT AND (GPL-3.0 OR Apache-2.0)", true),
+            ("(MIT", false),
+            ("Apache-2.0 WITH LLVM-exception", true),
+            ("GPL-2.0 WITH Classpath-exception-2.0", false),
+            ("GPL-2.0 WITH Classpath-exception-2.0 OR MIT", true),
+            ("MIT/Apache-2.0", true),
+            ("Unlicense/GPL-3.0", false),
+            ("", false),
+        ] {
+            assert_eq!(policy.allows(license), allowed, "{}", license);
+        }
+    }
+
+    #[test]
+    fn later_versions_of_allowed_licenses() {
+        let policy = LicensePolicy::new("GPL-2.0");
+        assert!(policy.allows("GPL-2.0+"));
+        assert!(!policy.allows("GPL-3.0+"));
+    }
+}
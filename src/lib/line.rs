@@ -0,0 +1,665 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+
+use crate::backend::IndexBackend;
+use crate::bump::{bump, BUMP_LEVELS};
+use crate::config::CargoConfig;
+use crate::error::{Error, Result};
+use crate::manifest::{
+    complete_locked, complete_member, complete_package_path, complete_precise, complete_removable,
+    complete_upgradable, find_manifest, package_version, DepKind,
+};
+use crate::remote::complete_ref;
+use crate::{complete_feature_ranked, complete_next_crate, Candidate, Kind, Options};
+
+/// Flags of cargo-edit followed by a value, either in the next word or after `=`.
+const VALUE_FLAGS: &[&str] = &[
+    "--branch",
+    "--bump",
+    "--exclude",
+    "--features",
+    "-F",
+    "--git",
+    "--manifest-path",
+    "--metadata",
+    "--package",
+    "-p",
+    "--path",
+    "--precise",
+    "-r",
+    "--registry",
+    "--rename",
+    "--rev",
+    "--tag",
+    "--target",
+    "--upgrade",
+    "--vers",
+];
+
+pub(crate) const FEATURE_FLAGS: &[&str] = &["--features", "-F"];
+
+/// Long flags of `cargo add` with their descriptions.
+const ADD_FLAGS: &[(&str, &str)] = &[
+    (
+        "--allow-prerelease",
+        "Include prerelease versions when fetching from crates.io",
+    ),
+    (
+        "--branch",
+        "Specify a git branch to download the crate from",
+    ),
+    ("--build", "Add crate as build dependency"),
+    ("--dev", "Add crate as development dependency"),
+    ("--features", "Space-separated list of features to add"),
+    (
+        "--git",
+        "Specify a git repository to download the crate from",
+    ),
+    (
+        "--manifest-path",
+        "Path to the manifest to add a dependency to",
+    ),
+    (
+        "--no-default-features",
+        "Set `default-features = false` for the added dependency",
+    ),
+    ("--offline", "Run without accessing the network"),
+    (
+        "--optional",
+        "Add as an optional dependency (for use in features)",
+    ),
+    (
+        "--package",
+        "Package id of the crate to add this dependency to",
+    ),
+    ("--path", "Specify the path the crate should be loaded from"),
+    ("--quiet", "Do not print any output in case of success"),
+    ("--registry", "Registry to use"),
+    ("--rename", "Rename a dependency in Cargo.toml"),
+    ("--rev", "Specify a git revision to download the crate from"),
+    ("--sort", "Keep dependencies sorted"),
+    ("--tag", "Specify a git tag to download the crate from"),
+    ("--target", "Add as dependency to the given target platform"),
+    ("--upgrade", "Choose method of semantic version upgrade"),
+    ("--vers", "Specify the version to grab from the registry"),
+];
+
+/// Long flags of `cargo rm` with their descriptions.
+const RM_FLAGS: &[(&str, &str)] = &[
+    ("--build", "Remove crate as build dependency"),
+    ("--dev", "Remove crate as development dependency"),
+    (
+        "--manifest-path",
+        "Path to the manifest to remove a dependency from",
+    ),
+    (
+        "--package",
+        "Package id of the crate to remove this dependency from",
+    ),
+    ("--quiet", "Do not print any output in case of success"),
+    (
+        "--target",
+        "Remove as dependency from the given target platform",
+    ),
+];
+
+/// Long flags of `cargo upgrade` with their descriptions.
+const UPGRADE_FLAGS: &[(&str, &str)] = &[
+    (
+        "--allow-prerelease",
+        "Include prerelease versions when fetching from crates.io",
+    ),
+    ("--dry-run", "Print changes to be made without making them"),
+    ("--exclude", "Crates to exclude and not upgrade"),
+    ("--manifest-path", "Path to the manifest to upgrade"),
+    ("--offline", "Run without accessing the network"),
+    (
+        "--package",
+        "Specify the package in the workspace to upgrade",
+    ),
+    (
+        "--skip-compatible",
+        "Only update a dependency if it is not semver compatible",
+    ),
+    (
+        "--to-lockfile",
+        "Upgrade all packages to the version in the lockfile",
+    ),
+    ("--workspace", "Upgrade all packages in the workspace"),
+];
+
+/// Methods accepted by `cargo add --upgrade`.
+const UPGRADE_METHODS: &[&str] = &["none", "patch", "minor", "all", "default"];
+
+/// Subcommands of cargo-edit whose command lines we complete.
+const SUBCOMMANDS: &[&str] = &["add", "rm", "remove", "upgrade", "set-version", "update"];
+
+/// The word under the cursor of a cargo-edit command line.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Token<'a> {
+    /// A crate name, or `name@version` requirement.
+    Crate(&'a str),
+    /// A comma-separated feature list of the crate `spec`. `prefix` is the part of the word
+    /// before the feature being typed.
+    Feature {
+        spec: &'a str,
+        prefix: &'a str,
+        partial: &'a str,
+    },
+    /// The value of `flag`. `prefix` is the part of the word before the value, e.g. `--flag=`.
+    FlagValue {
+        flag: &'a str,
+        prefix: &'a str,
+        partial: &'a str,
+    },
+    /// A flag being typed.
+    Flag(&'a str),
+    /// A feature list without a crate to resolve it against, or the subcommand itself.
+    Other,
+}
+
+/// A cargo-edit command line cut at the cursor.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Invocation<'a> {
+    /// `add`, `rm` or `upgrade`, if recognized.
+    pub subcommand: Option<&'a str>,
+    /// Flags before the cursor, without their values.
+    pub flags: Vec<&'a str>,
+    /// Values of the flags before the cursor.
+    pub values: Vec<(&'a str, &'a str)>,
+    /// Positional package specs before the cursor.
+    pub specs: Vec<&'a str>,
+    /// The word under the cursor.
+    pub token: Token<'a>,
+}
+
+/// Split a command line into words; a trailing space starts a new, empty word.
+pub fn split_words(line: &str) -> Vec<&str> {
+    let mut words: Vec<&str> = line.split_whitespace().collect();
+    if words.is_empty() || line.ends_with(char::is_whitespace) {
+        words.push("");
+    }
+    words
+}
+
+/// Cut a command line at a cursor offset counted in characters, like bash's `COMP_POINT`.
+pub fn cut_at(line: &str, point: usize) -> &str {
+    match line.char_indices().nth(point) {
+        Some((idx, _)) => &line[..idx],
+        None => line,
+    }
+}
+
+/// Split off the `cargo add` / `cargo-add` / `add` command itself.
+fn split_command<'a, 'b>(words: &'b [&'a str]) -> (Option<&'a str>, &'b [&'a str]) {
+    match words {
+        ["cargo", subcommand, rest @ ..] => (Some(*subcommand), rest),
+        [command, rest @ ..] if command.starts_with("cargo-") => {
+            (command.strip_prefix("cargo-"), rest)
+        }
+        [command, rest @ ..] if SUBCOMMANDS.contains(command) => (Some(*command), rest),
+        _ => (None, words),
+    }
+}
+
+/// Parse a command line cut at the cursor.
+pub fn parse(line: &str) -> Invocation<'_> {
+    let words = split_words(line);
+    let (current, previous) = words.split_last().unwrap();
+    let (subcommand, previous) = split_command(previous);
+    if previous == ["cargo"] {
+        // The cursor is still on the subcommand.
+        return Invocation {
+            subcommand: None,
+            flags: vec![],
+            values: vec![],
+            specs: vec![],
+            token: Token::Other,
+        };
+    }
+
+    let mut flags = vec![];
+    let mut values = vec![];
+    let mut specs = vec![];
+    let mut pending_flag = None;
+    for word in previous {
+        if let Some(flag) = pending_flag.take() {
+            values.push((flag, *word));
+            continue;
+        }
+        if word.starts_with('-') {
+            match word.split_once('=') {
+                Some((flag, value)) => {
+                    flags.push(flag);
+                    values.push((flag, value));
+                }
+                None => {
+                    flags.push(*word);
+                    if VALUE_FLAGS.contains(word) {
+                        pending_flag = Some(*word);
+                    }
+                }
+            }
+        } else {
+            specs.push(*word);
+        }
+    }
+
+    let value = match pending_flag {
+        Some(flag) => Some((flag, "", *current)),
+        None => VALUE_FLAGS.iter().find_map(|flag| {
+            let value = current.strip_prefix(flag)?.strip_prefix('=')?;
+            Some((*flag, &current[..current.len() - value.len()], value))
+        }),
+    };
+    let token = match value {
+        Some((flag, prefix, list)) if FEATURE_FLAGS.contains(&flag) => match specs.last() {
+            Some(spec) => {
+                let (prefix, partial) = match list.rfind(',') {
+                    Some(idx) => current.split_at(prefix.len() + idx + 1),
+                    None => (prefix, list),
+                };
+                Token::Feature {
+                    spec,
+                    prefix,
+                    partial,
+                }
+            }
+            None => Token::Other,
+        },
+        Some((flag, prefix, partial)) => Token::FlagValue {
+            flag,
+            prefix,
+            partial,
+        },
+        None if current.starts_with('-') => Token::Flag(current),
+        None => Token::Crate(current),
+    };
+
+    Invocation {
+        subcommand,
+        flags,
+        values,
+        specs,
+        token,
+    }
+}
+
+impl<'a> Invocation<'a> {
+    /// Whether any of the given flags was passed before the cursor.
+    pub fn has_flag(&self, names: &[&str]) -> bool {
+        self.flags.iter().any(|flag| names.contains(flag))
+    }
+
+    /// The manifest the command acts on, from `--manifest-path` or the directory `cwd`.
+    pub fn manifest(&self, cwd: &Path) -> Result<PathBuf> {
+        match self.value_of("--manifest-path") {
+            Some(path) => Ok(cwd.join(path)),
+            None => find_manifest(cwd).ok_or_else(|| Error::ManifestMissing(cwd.to_path_buf())),
+        }
+    }
+
+    /// Kind of the dependencies the command acts on, after `--dev` and `--build`.
+    pub fn dep_kind(&self) -> DepKind {
+        if self.has_flag(&["--dev", "-D"]) {
+            DepKind::Dev
+        } else if self.has_flag(&["--build", "-B"]) {
+            DepKind::Build
+        } else {
+            DepKind::Normal
+        }
+    }
+
+    /// The value given to a flag before the cursor, the last one if given several times.
+    pub fn value_of(&self, flag: &str) -> Option<&'a str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == flag)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Classify the last word of a command line cut at the cursor.
+pub fn token_at(line: &str) -> Token<'_> {
+    parse(line).token
+}
+
+/// Long flags of `cargo set-version` with their descriptions.
+const SET_VERSION_FLAGS: &[(&str, &str)] = &[
+    ("--bump", "Increment manifest version"),
+    ("--dry-run", "Print changes to be made without making them"),
+    ("--exclude", "Crates to exclude and not modify"),
+    ("--manifest-path", "Path to the manifest to modify"),
+    ("--metadata", "Specify the version metadata field"),
+    (
+        "--package",
+        "Package id of the crate to change the version of",
+    ),
+    ("--workspace", "Modify all packages in the workspace"),
+];
+
+/// Long flags of `cargo update` with their descriptions.
+const UPDATE_FLAGS: &[(&str, &str)] = &[
+    (
+        "--aggressive",
+        "Force updating all dependencies of the package as well",
+    ),
+    ("--dry-run", "Don't actually write the lockfile"),
+    ("--manifest-path", "Path to Cargo.toml"),
+    ("--offline", "Run without accessing the network"),
+    ("--package", "Package to update"),
+    (
+        "--precise",
+        "Update a single dependency to exactly the given version",
+    ),
+    ("--workspace", "Only update the workspace packages"),
+];
+
+/// Long flags of a cargo-edit subcommand with their descriptions.
+pub fn flags(subcommand: &str) -> &'static [(&'static str, &'static str)] {
+    match subcommand {
+        "add" => ADD_FLAGS,
+        "rm" | "remove" => RM_FLAGS,
+        "upgrade" => UPGRADE_FLAGS,
+        "set-version" => SET_VERSION_FLAGS,
+        "update" => UPDATE_FLAGS,
+        _ => &[],
+    }
+}
+
+/// Whether a flag is followed by a value, either in the next word or after `=`.
+pub fn takes_value(flag: &str) -> bool {
+    VALUE_FLAGS.contains(&flag)
+}
+
+/// Complete a flag of a cargo-edit subcommand, leaving out the ones already given.
+pub fn complete_flag(subcommand: &str, given: &[&str], partial: &str) -> Vec<Candidate> {
+    flags(subcommand)
+        .iter()
+        .filter(|(flag, _)| flag.starts_with(partial))
+        .filter(|(flag, _)| takes_value(flag) || !given.contains(flag))
+        .map(|(flag, description)| Candidate {
+            value: flag.to_string(),
+            display: None,
+            description: Some(description.to_string()),
+            score: None,
+            kind: Kind::Flag,
+            yanked: false,
+        })
+        .collect()
+}
+
+/// Versions `cargo set-version` could move the package at `manifest` to, as `(level, version)`.
+fn next_versions(manifest: &Path) -> Vec<(&'static str, String)> {
+    let current = match package_version(manifest).and_then(|v| Version::parse(&v).ok()) {
+        Some(current) => current,
+        None => return vec![],
+    };
+    BUMP_LEVELS
+        .iter()
+        .filter_map(|level| Some((*level, bump(&current, level)?.to_string())))
+        .collect()
+}
+
+/// Complete the version of `cargo set-version <version>`, described by the bump leading to it.
+pub fn complete_set_version(manifest: &Path) -> Vec<Candidate> {
+    next_versions(manifest)
+        .into_iter()
+        .map(|(level, version)| Candidate {
+            value: version,
+            display: None,
+            description: Some(level.to_string()),
+            score: None,
+            kind: Kind::Version,
+            yanked: false,
+        })
+        .collect()
+}
+
+/// Complete the level of `cargo set-version --bump`, described by the version it leads to.
+pub fn complete_bump(manifest: &Path) -> Vec<Candidate> {
+    let next: Vec<_> = next_versions(manifest);
+    BUMP_LEVELS
+        .iter()
+        .map(|level| Candidate {
+            value: level.to_string(),
+            display: None,
+            description: next
+                .iter()
+                .find(|(next_level, _)| next_level == level)
+                .map(|(_, version)| version.clone()),
+            score: None,
+            kind: Kind::Value,
+            yanked: false,
+        })
+        .collect()
+}
+
+fn prefixed(prefix: &str, candidates: Vec<Candidate>, partial: &str) -> Vec<Candidate> {
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.value.starts_with(partial))
+        .map(|candidate| Candidate {
+            value: format!("{}{}", prefix, candidate.value),
+            display: Some(candidate.label().to_string()),
+            ..candidate
+        })
+        .collect()
+}
+
+fn complete_flag_value(
+    index: &dyn IndexBackend,
+    config: &CargoConfig,
+    invocation: &Invocation,
+    flag: &str,
+    partial: &str,
+) -> Result<Vec<Candidate>> {
+    Ok(match flag {
+        "--branch" | "--tag" | "--rev" => match invocation.value_of("--git") {
+            Some(url) => complete_ref(url, flag)?,
+            None => vec![],
+        },
+        "--path" => complete_package_path(config.cwd(), partial)?,
+        "--bump" => complete_bump(&invocation.manifest(config.cwd())?),
+        "--precise" => match invocation
+            .value_of("--package")
+            .or(invocation.value_of("-p"))
+        {
+            Some(package) => complete_precise(index, &invocation.manifest(config.cwd())?, package)?,
+            None => vec![],
+        },
+        "--package" | "-p" if invocation.subcommand == Some("update") => {
+            complete_locked(&invocation.manifest(config.cwd())?, partial)?
+        }
+        "--package" | "-p" => complete_member(&invocation.manifest(config.cwd())?, partial)?,
+        "--registry" => config
+            .registry_names()
+            .into_iter()
+            .map(|name| Candidate {
+                description: config.registry_index(&name).ok().or_else(|| {
+                    env::var(format!("CARGO_REGISTRIES_{}_INDEX", name.to_uppercase())).ok()
+                }),
+                value: name,
+                display: None,
+                score: None,
+                kind: Kind::Value,
+                yanked: false,
+            })
+            .collect(),
+        "--upgrade" => UPGRADE_METHODS
+            .iter()
+            .map(|method| Candidate {
+                value: method.to_string(),
+                display: None,
+                description: None,
+                score: None,
+                kind: Kind::Value,
+                yanked: false,
+            })
+            .collect(),
+        _ => vec![],
+    })
+}
+
+/// Complete the word under the cursor of a cargo-edit command line cut at the cursor.
+pub fn complete_line(
+    index: &dyn IndexBackend,
+    config: &CargoConfig,
+    options: &Options,
+    line: &str,
+) -> Result<Vec<Candidate>> {
+    let invocation = parse(line);
+    match invocation.token {
+        Token::Crate(partial) => match invocation.subcommand {
+            Some("rm" | "remove") => Ok(complete_removable(
+                &invocation.manifest(config.cwd())?,
+                invocation.dep_kind(),
+                invocation.value_of("--target"),
+                partial,
+            )?
+            .into_iter()
+            .filter(|candidate| !invocation.specs.contains(&candidate.value.as_str()))
+            .collect()),
+            Some("set-version") => Ok(prefixed(
+                "",
+                complete_set_version(&invocation.manifest(config.cwd())?),
+                partial,
+            )),
+            Some("upgrade") => complete_upgradable(
+                &invocation.manifest(config.cwd())?,
+                Some(index),
+                partial,
+                options,
+            ),
+            _ => Ok(complete_next_crate(
+                index,
+                &invocation.specs,
+                partial,
+                options,
+            )?),
+        },
+        Token::Feature {
+            spec,
+            prefix,
+            partial,
+        } => {
+            let (name, version) = spec.split_once('@').unwrap_or((spec, ""));
+            Ok(prefixed(
+                prefix,
+                complete_feature_ranked(index, name, version, partial, options)?,
+                "",
+            ))
+        }
+        Token::FlagValue {
+            flag,
+            prefix,
+            partial,
+        } => Ok(prefixed(
+            prefix,
+            complete_flag_value(index, config, &invocation, flag, partial)?,
+            partial,
+        )),
+        Token::Flag(partial) => Ok(match invocation.subcommand {
+            Some(subcommand) => complete_flag(subcommand, &invocation.flags, partial),
+            None => vec![],
+        }),
+        Token::Other => Ok(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_words_trailing_space() {
+        assert_eq!(split_words("cargo add serde"), ["cargo", "add", "serde"]);
+        assert_eq!(
+            split_words("cargo add serde "),
+            ["cargo", "add", "serde", ""]
+        );
+        assert_eq!(split_words("cargo  add\tserde"), ["cargo", "add", "serde"]);
+        assert_eq!(split_words(""), [""]);
+    }
+
+    #[test]
+    fn features_after_equals() {
+        assert_eq!(
+            token_at("cargo add serde --features=derive,r"),
+            Token::Feature {
+                spec: "serde",
+                prefix: "--features=derive,",
+                partial: "r",
+            }
+        );
+        assert_eq!(
+            token_at("cargo add serde --features="),
+            Token::Feature {
+                spec: "serde",
+                prefix: "--features=",
+                partial: "",
+            }
+        );
+    }
+
+    #[test]
+    fn features_in_next_word() {
+        assert_eq!(
+            token_at("cargo add serde -F x"),
+            Token::Feature {
+                spec: "serde",
+                prefix: "",
+                partial: "x",
+            }
+        );
+        assert_eq!(
+            token_at("cargo add tokio@1 -F rt,ma"),
+            Token::Feature {
+                spec: "tokio@1",
+                prefix: "rt,",
+                partial: "ma",
+            }
+        );
+        // Without a crate there is nothing to resolve the features against.
+        assert_eq!(token_at("cargo add -F x"), Token::Other);
+    }
+
+    #[test]
+    fn flag_values() {
+        assert_eq!(
+            token_at("cargo add serde --registry my"),
+            Token::FlagValue {
+                flag: "--registry",
+                prefix: "",
+                partial: "my",
+            }
+        );
+        assert_eq!(
+            token_at("cargo add serde --rename=se"),
+            Token::FlagValue {
+                flag: "--rename",
+                prefix: "--rename=",
+                partial: "se",
+            }
+        );
+        let invocation = parse("cargo add --registry mycorp --dev serde ");
+        assert_eq!(invocation.subcommand, Some("add"));
+        assert_eq!(invocation.flags, ["--registry", "--dev"]);
+        assert_eq!(invocation.values, [("--registry", "mycorp")]);
+        assert_eq!(invocation.specs, ["serde"]);
+        assert_eq!(invocation.token, Token::Crate(""));
+    }
+
+    #[test]
+    fn flags_and_crates() {
+        assert_eq!(token_at("cargo add --de"), Token::Flag("--de"));
+        assert_eq!(token_at("cargo add ser"), Token::Crate("ser"));
+        assert_eq!(token_at("cargo-add serde@1."), Token::Crate("serde@1."));
+        // After a flag without a value, the next word is a crate again.
+        assert_eq!(token_at("cargo add --dev "), Token::Crate(""));
+        assert_eq!(token_at("cargo ad"), Token::Other);
+    }
+}
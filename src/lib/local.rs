@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use semver::Version;
+use serde::Deserialize;
+
+use crate::backend::IndexBackend;
+use crate::config::read_toml;
+use crate::crates::{regexify, tree_state, Crate, Dependency};
+use crate::error::Result;
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: Package,
+    #[serde(default)]
+    features: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    dependencies: HashMap<String, ManifestDependency>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManifestDependency {
+    Version(String),
+    Detailed {
+        #[serde(default)]
+        version: Option<String>,
+        #[serde(default)]
+        optional: bool,
+        #[serde(default)]
+        package: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    version: String,
+    #[serde(default, rename = "rust-version")]
+    rust_version: Option<String>,
+    #[serde(default)]
+    edition: Option<String>,
+    #[serde(default)]
+    license: Option<String>,
+}
+
+/// Crates unpacked into a directory, as produced by `cargo vendor`.
+///
+/// Each subdirectory holds one crate version, named either `<name>` or `<name>-<version>`.
+pub struct VendorIndex {
+    path: PathBuf,
+}
+
+impl VendorIndex {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Manifests of all vendored packages grouped by crate name.
+    fn packages(&self) -> Result<HashMap<String, Vec<PathBuf>>> {
+        let mut packages: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for entry in self.path.read_dir()? {
+            let manifest = entry?.path().join("Cargo.toml");
+            if let Ok(name) = package_name(&manifest) {
+                packages.entry(name).or_default().push(manifest);
+            }
+        }
+        Ok(packages)
+    }
+}
+
+impl IndexBackend for VendorIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let matcher = regexify(prefix);
+        Ok(self
+            .packages()?
+            .into_keys()
+            .filter(|name| matcher.is_match(name))
+            .sorted()
+            .collect())
+    }
+
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        self.packages()?
+            .remove(name)
+            .map(|manifests| read_manifests(&manifests))
+            .transpose()
+    }
+
+    fn state(&self) -> Option<String> {
+        // Vendoring replaces the directories of crates, touching the vendor directory.
+        tree_state(&self.path, 1)
+    }
+}
+
+fn package_name(manifest: &Path) -> Result<String> {
+    Ok(read_toml::<Manifest>(manifest)?.package.name)
+}
+
+/// Versions described by the manifests of a vendored crate, oldest first like in the index.
+fn read_manifests(manifests: &[PathBuf]) -> Result<Vec<Crate>> {
+    let mut crates: Vec<Crate> = manifests
+        .iter()
+        .map(|manifest| {
+            let manifest: Manifest = read_toml(manifest)?;
+            Ok(Crate {
+                name: manifest.package.name,
+                version: manifest.package.version,
+                deps: manifest
+                    .dependencies
+                    .into_iter()
+                    .map(|(name, dep)| match dep {
+                        ManifestDependency::Version(req) => Dependency {
+                            name,
+                            req,
+                            optional: false,
+                            package: None,
+                        },
+                        ManifestDependency::Detailed {
+                            version,
+                            optional,
+                            package,
+                        } => Dependency {
+                            name,
+                            req: version.unwrap_or_else(|| "*".to_string()),
+                            optional,
+                            package,
+                        },
+                    })
+                    .collect::<Vec<_>>()
+                    .into(),
+                features: manifest.features.into(),
+                yanked: false,
+                rust_version: manifest.package.rust_version,
+                edition: manifest.package.edition,
+                license: manifest.package.license,
+            })
+        })
+        .collect::<Result<_>>()?;
+    crates.sort_by_cached_key(|crate_| Version::parse(&crate_.version).ok());
+    Ok(crates)
+}
@@ -0,0 +1,439 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use regex::Regex;
+use semver::{Version, VersionReq};
+use toml::Value;
+
+use crate::backend::IndexBackend;
+use crate::config::read_toml;
+use crate::error::Result;
+use crate::{complete_crate_described, latest_version, Candidate, Kind, Options};
+
+/// Kind of a dependency, after the table it is declared in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DepKind {
+    Normal,
+    Dev,
+    Build,
+}
+
+impl DepKind {
+    fn table(self) -> &'static str {
+        match self {
+            DepKind::Normal => "dependencies",
+            DepKind::Dev => "dev-dependencies",
+            DepKind::Build => "build-dependencies",
+        }
+    }
+}
+
+/// A dependency declared by a manifest.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Dependency {
+    /// Key of the dependency, which differs from the package for renamed dependencies.
+    pub name: String,
+    /// The crate actually depended on.
+    pub package: String,
+    /// Version requirement, absent for path and git dependencies without one.
+    pub requirement: Option<String>,
+    /// Features enabled in addition to the default ones.
+    pub features: Vec<String>,
+    pub kind: DepKind,
+    /// Platform of a `[target.<cfg>.dependencies]` table.
+    pub target: Option<String>,
+}
+
+/// The manifest of the package `cwd` is in.
+pub fn find_manifest(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors()
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|path| path.is_file())
+}
+
+fn read_manifest(manifest: &Path) -> Result<Value> {
+    read_toml(manifest)
+}
+
+fn table_dependencies(table: &Value, target: Option<&str>, deps: &mut Vec<Dependency>) {
+    for kind in [DepKind::Normal, DepKind::Dev, DepKind::Build] {
+        let entries = match table.get(kind.table()).and_then(Value::as_table) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        for (name, entry) in entries {
+            let requirement = match entry {
+                Value::String(requirement) => Some(requirement.clone()),
+                entry => entry
+                    .get("version")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            };
+            deps.push(Dependency {
+                name: name.clone(),
+                package: entry
+                    .get("package")
+                    .and_then(Value::as_str)
+                    .unwrap_or(name)
+                    .to_string(),
+                requirement,
+                features: entry
+                    .get("features")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|feature| feature.as_str().map(str::to_string))
+                    .collect(),
+                kind,
+                target: target.map(str::to_string),
+            });
+        }
+    }
+}
+
+/// All dependencies declared by a manifest, including platform specific ones and those shared
+/// through `[workspace.dependencies]`.
+pub fn dependencies(manifest: &Path) -> Result<Vec<Dependency>> {
+    let manifest = read_manifest(manifest)?;
+    let mut deps = vec![];
+    table_dependencies(&manifest, None, &mut deps);
+    if let Some(workspace) = manifest.get("workspace") {
+        table_dependencies(workspace, None, &mut deps);
+    }
+    if let Some(targets) = manifest.get("target").and_then(Value::as_table) {
+        for (target, table) in targets {
+            table_dependencies(table, Some(target), &mut deps);
+        }
+    }
+    Ok(deps)
+}
+
+/// The root manifest of the workspace `manifest` belongs to, or `manifest` itself outside one.
+pub fn workspace_root(manifest: &Path) -> PathBuf {
+    manifest
+        .parent()
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|candidate| {
+            read_manifest(candidate).is_ok_and(|value| value.get("workspace").is_some())
+        })
+        .unwrap_or_else(|| manifest.to_path_buf())
+}
+
+/// Expand a `members` pattern relative to `root`, supporting `*` and `?` in any component.
+fn expand_member(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+    for component in pattern.split('/').filter(|component| !component.is_empty()) {
+        if !component.contains(&['*', '?'][..]) {
+            dirs = dirs.into_iter().map(|dir| dir.join(component)).collect();
+            continue;
+        }
+        let matcher = Regex::new(&format!(
+            "^{}$",
+            regex::escape(component)
+                .replace("\\*", ".*")
+                .replace("\\?", ".")
+        ))
+        .unwrap();
+        dirs = dirs
+            .iter()
+            .filter_map(|dir| dir.read_dir().ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| matcher.is_match(&entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .sorted()
+            .collect();
+    }
+    dirs
+}
+
+/// Manifests of all packages in the workspace of `manifest`, the root one first.
+pub fn workspace_manifests(manifest: &Path) -> Result<Vec<PathBuf>> {
+    let root = workspace_root(manifest);
+    let value = read_manifest(&root)?;
+    let dir = root.parent().unwrap_or_else(|| Path::new("."));
+    let patterns = |key: &str| -> Vec<String> {
+        value
+            .get("workspace")
+            .and_then(|workspace| workspace.get(key))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|pattern| pattern.as_str().map(str::to_string))
+            .collect()
+    };
+    let excluded: Vec<PathBuf> = patterns("exclude")
+        .iter()
+        .flat_map(|pattern| expand_member(dir, pattern))
+        .collect();
+    let members = patterns("members")
+        .iter()
+        .flat_map(|pattern| expand_member(dir, pattern))
+        .filter(|member| !excluded.contains(member))
+        .map(|member| member.join("Cargo.toml"))
+        .filter(|member| member.is_file() && *member != root)
+        .unique()
+        .collect::<Vec<_>>();
+    Ok(std::iter::once(root).chain(members).collect())
+}
+
+/// Complete the name of a workspace member for `-p`, described by its directory.
+pub fn complete_member(manifest: &Path, partial: &str) -> Result<Vec<Candidate>> {
+    let manifests = workspace_manifests(manifest)?;
+    let root = manifests[0].parent().unwrap_or_else(|| Path::new(""));
+    Ok(manifests
+        .iter()
+        .filter_map(|member| {
+            let name = package_name(member)?;
+            let dir = member.parent()?.strip_prefix(root).ok()?;
+            Some(Candidate {
+                value: name,
+                display: None,
+                description: Some(if dir.as_os_str().is_empty() {
+                    ".".to_string()
+                } else {
+                    // The same on every platform rather than with backslashes on Windows.
+                    dir.iter().map(|part| part.to_string_lossy()).join("/")
+                }),
+                score: None,
+                kind: Kind::Crate,
+                yanked: false,
+            })
+        })
+        .filter(|candidate| candidate.value.starts_with(partial))
+        .sorted_by(|a, b| a.value.cmp(&b.value))
+        .collect())
+}
+
+/// Complete a `name@version` spec of a dependency of the workspace for `cargo upgrade`.
+///
+/// The specs carry the current requirement; given an index, they are annotated with the
+/// latest version available, and versions are completed after the `@`.
+pub fn complete_upgradable(
+    manifest: &Path,
+    index: Option<&dyn IndexBackend>,
+    partial: &str,
+    options: &Options,
+) -> Result<Vec<Candidate>> {
+    if let (Some(index), true) = (index, partial.contains('@')) {
+        return complete_crate_described(index, partial, options);
+    }
+    let mut deps = vec![];
+    for member in workspace_manifests(manifest)? {
+        deps.extend(dependencies(&member)?);
+    }
+    Ok(deps
+        .into_iter()
+        .filter_map(|dep| Some(format!("{}@{}", dep.package, dep.requirement?)))
+        .filter(|spec| spec.starts_with(partial))
+        .sorted()
+        .dedup()
+        .map(|spec| Candidate {
+            description: index.and_then(|index| {
+                let (name, _) = spec.split_once('@')?;
+                latest_version(index, name).map(|version| format!("latest {}", version))
+            }),
+            value: spec,
+            display: None,
+            score: None,
+            kind: Kind::Crate,
+            yanked: false,
+        })
+        .collect())
+}
+
+/// Packages locked in the `Cargo.lock` of the workspace of `manifest`, as `(name, version)`.
+pub fn locked_packages(manifest: &Path) -> Result<Vec<(String, String)>> {
+    let root = workspace_root(manifest);
+    let lock = read_manifest(&root.with_file_name("Cargo.lock"))?;
+    Ok(lock
+        .get("package")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            Some((
+                package.get("name")?.as_str()?.to_string(),
+                package.get("version")?.as_str()?.to_string(),
+            ))
+        })
+        .collect())
+}
+
+/// Complete a package of `Cargo.lock` for `cargo update -p`, described by its locked version.
+pub fn complete_locked(manifest: &Path, partial: &str) -> Result<Vec<Candidate>> {
+    Ok(locked_packages(manifest)?
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(partial))
+        .sorted()
+        .map(|(name, version)| Candidate {
+            value: name,
+            display: None,
+            description: Some(version),
+            score: None,
+            kind: Kind::Crate,
+            yanked: false,
+        })
+        .collect())
+}
+
+/// Complete `cargo update -p <package> --precise` with the versions in the index satisfying every
+/// requirement the workspace places on the package, newest first.
+pub fn complete_precise(
+    index: &dyn IndexBackend,
+    manifest: &Path,
+    package: &str,
+) -> Result<Vec<Candidate>> {
+    let name = package.split_once('@').map_or(package, |(name, _)| name);
+    let mut requirements = vec![];
+    for member in workspace_manifests(manifest)? {
+        requirements.extend(
+            dependencies(&member)?
+                .into_iter()
+                .filter(|dep| dep.package == name)
+                .filter_map(|dep| VersionReq::parse(dep.requirement.as_deref()?).ok()),
+        );
+    }
+    let locked: Vec<String> = locked_packages(manifest)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(locked, _)| locked == name)
+        .map(|(_, version)| version)
+        .collect();
+    Ok(index
+        .versions(name)?
+        .unwrap_or_default()
+        .into_iter()
+        .rev()
+        .filter(|crate_| !crate_.yanked)
+        .filter(|crate_| {
+            Version::parse(&crate_.version)
+                .is_ok_and(|version| requirements.iter().all(|req| req.matches(&version)))
+        })
+        .map(|crate_| Candidate {
+            description: locked
+                .contains(&crate_.version)
+                .then(|| "locked".to_string()),
+            value: crate_.version,
+            display: None,
+            score: None,
+            kind: Kind::Version,
+            yanked: false,
+        })
+        .collect())
+}
+
+/// Complete a feature the manifest currently enables for a dependency, in any of its tables.
+pub fn complete_enabled_features(
+    manifest: &Path,
+    dependency: &str,
+    partial: &str,
+) -> Result<Vec<Candidate>> {
+    Ok(dependencies(manifest)?
+        .into_iter()
+        .filter(|dep| dep.name == dependency)
+        .flat_map(|dep| dep.features)
+        .filter(|feature| feature.starts_with(partial))
+        .sorted()
+        .dedup()
+        .map(|feature| Candidate {
+            value: feature,
+            display: None,
+            description: None,
+            score: None,
+            kind: Kind::Feature,
+            yanked: false,
+        })
+        .collect())
+}
+
+/// Complete a dependency `cargo rm` can remove from the `kind` table of `target`.
+pub fn complete_removable(
+    manifest: &Path,
+    kind: DepKind,
+    target: Option<&str>,
+    partial: &str,
+) -> Result<Vec<Candidate>> {
+    Ok(dependencies(manifest)?
+        .into_iter()
+        .filter(|dep| dep.kind == kind && dep.target.as_deref() == target)
+        .filter(|dep| dep.name.starts_with(partial))
+        .map(|dep| Candidate {
+            description: match (&dep.requirement, dep.name != dep.package) {
+                (Some(requirement), true) => Some(format!("{} {}", dep.package, requirement)),
+                (Some(requirement), false) => Some(requirement.clone()),
+                (None, true) => Some(dep.package),
+                (None, false) => None,
+            },
+            value: dep.name,
+            display: None,
+            score: None,
+            kind: Kind::Crate,
+            yanked: false,
+        })
+        .sorted_by(|a, b| a.value.cmp(&b.value))
+        .collect())
+}
+
+/// Version of the package declared by a `Cargo.toml`, following `version.workspace = true`.
+pub fn package_version(manifest: &Path) -> Option<String> {
+    package_field(manifest, "version")
+}
+
+/// Edition of the package declared by a `Cargo.toml`, following `edition.workspace = true`.
+pub fn package_edition(manifest: &Path) -> Option<String> {
+    package_field(manifest, "edition")
+}
+
+fn package_field(manifest: &Path, key: &str) -> Option<String> {
+    let value = read_manifest(manifest).ok()?;
+    let field = value.get("package")?.get(key)?;
+    match field.as_str() {
+        Some(field) => Some(field.to_string()),
+        None => {
+            let root = read_manifest(&workspace_root(manifest)).ok()?;
+            let field = root.get("workspace")?.get("package")?.get(key)?;
+            Some(field.as_str()?.to_string())
+        }
+    }
+}
+
+/// Name of the package declared by a `Cargo.toml`, if it declares one.
+pub fn package_name(manifest: &Path) -> Option<String> {
+    let manifest: Value = fs::read_to_string(manifest).ok()?.parse().ok()?;
+    Some(manifest.get("package")?.get("name")?.as_str()?.to_string())
+}
+
+/// Complete a directory for `cargo add --path`.
+///
+/// Directories holding a package come first, described by the package name.
+pub fn complete_package_path(cwd: &Path, partial: &str) -> Result<Vec<Candidate>> {
+    // Windows users may type either separator; completions keep what was typed and add `/`.
+    let (dir, file) = match partial.rfind(std::path::is_separator) {
+        Some(idx) => partial.split_at(idx + 1),
+        None => ("", partial),
+    };
+    let read_dir = cwd.join(dir);
+    Ok(read_dir
+        .read_dir()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        // Hidden directories only show up once asked for.
+        .filter(|name| name.starts_with(file) && (file.starts_with('.') || !name.starts_with('.')))
+        .map(|name| Candidate {
+            description: package_name(&read_dir.join(&name).join("Cargo.toml")),
+            value: format!("{}{}/", dir, name),
+            display: None,
+            score: None,
+            kind: Kind::Value,
+            yanked: false,
+        })
+        .sorted_by(|a, b| {
+            (a.description.is_none(), &a.value).cmp(&(b.description.is_none(), &b.value))
+        })
+        .collect())
+}
@@ -0,0 +1,200 @@
+/// Edit distance between two strings, counting a swap of adjacent characters as one edit.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Rows of the distance matrix: two back, one back and the current one.
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            current[j] = substitution.min(previous[j] + 1).min(current[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+/// How typed text selects crate names.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum MatchMode {
+    /// Names starting with the text.
+    #[default]
+    Prefix,
+    /// Names containing the characters of the text in order, e.g. `tksrl` for `tokio-serial`.
+    Fuzzy,
+    /// Names whose words start with the parts of the text, e.g. `sd` for `serde_derive`.
+    Abbreviation,
+    /// Names containing the text anywhere, e.g. `json` for `serde_json`.
+    Contains,
+    /// Names matching the text as a regular expression, searching the whole index.
+    Regex,
+}
+
+/// Whether prefixes match crate names regardless of case.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum CaseSensitivity {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Ignore case unless the text has an uppercase letter, like vim's `smartcase`.
+    Smart,
+}
+
+impl CaseSensitivity {
+    pub fn ignores_case(self, query: &str) -> bool {
+        match self {
+            CaseSensitivity::Sensitive => false,
+            CaseSensitivity::Insensitive => true,
+            CaseSensitivity::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+/// Characters of a crate name as compared, ignoring case and telling `-` and `_` not apart.
+fn normalized(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|char| {
+            if char == '-' {
+                '_'
+            } else {
+                char.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+/// Score of a fuzzy match of `query` against `name`, higher being better, or `None` if the
+/// characters of `query` don't all appear in `name` in order.
+///
+/// Like fzf, matches at the start of words and runs of consecutive characters score higher and
+/// characters skipped in between cost a little.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i64> {
+    let query = normalized(query);
+    let name = normalized(name);
+    if query.is_empty() {
+        return Some(0);
+    }
+    let bonus = |j: usize| 16 + if j == 0 || name[j - 1] == '_' { 8 } else { 0 };
+    // best[j]: the best score with the current query character matched at `name[j]`.
+    let mut best: Vec<Option<i64>> = name
+        .iter()
+        .enumerate()
+        .map(|(j, char)| (*char == query[0]).then(|| bonus(j) - j as i64))
+        .collect();
+    for query_char in &query[1..] {
+        best = (0..name.len())
+            .map(|j| {
+                if name[j] != *query_char {
+                    return None;
+                }
+                (0..j)
+                    .filter_map(|k| {
+                        let consecutive = if k + 1 == j { 8 } else { 0 };
+                        Some(best[k]? + consecutive - (j - k - 1) as i64)
+                    })
+                    .max()
+                    .map(|score| score + bonus(j))
+            })
+            .collect();
+    }
+    best.into_iter().flatten().max()
+}
+
+/// How many words `query` leaves out when abbreviating `words`, if it does: it has to split into
+/// prefixes of words in order, skipping any but the first word.
+fn abbreviates(query: &[char], words: &[&[char]], first: bool) -> Option<usize> {
+    if query.is_empty() {
+        // Words left over at the end count as skipped, so exact abbreviations rank first.
+        return Some(words.len());
+    }
+    let (word, rest) = words.split_first()?;
+    let common = word.iter().zip(query).take_while(|(a, b)| a == b).count();
+    let used = (1..=common).filter_map(|taken| abbreviates(&query[taken..], rest, false));
+    let skipped = (!first)
+        .then(|| abbreviates(query, rest, false).map(|skipped| skipped + 1))
+        .flatten();
+    used.chain(skipped).min()
+}
+
+/// Score of `query` as an abbreviation of the `-` or `_` separated words of `name`, higher being
+/// better, e.g. `sd` for `serde_derive` or `tokser` for `tokio-serial`.
+pub fn abbreviation_score(query: &str, name: &str) -> Option<i64> {
+    let query = normalized(query);
+    let name = normalized(name);
+    let words: Vec<&[char]> = name.split(|char| *char == '_').collect();
+    abbreviates(&query, &words, true).map(|skipped| -(skipped as i64))
+}
+
+/// Score of a crate name containing `query`, higher the earlier it appears, e.g. `json` is found
+/// in `json5` before `serde_json`.
+pub fn contains_score(query: &str, name: &str, ignore_case: bool) -> Option<i64> {
+    let fold = |text: &str| {
+        let text = text.replace('-', "_");
+        match ignore_case {
+            true => text.to_lowercase(),
+            false => text,
+        }
+    };
+    fold(name).find(&fold(query)).map(|start| -(start as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("serde", "serde"), 0);
+        assert_eq!(edit_distance("", "serde"), 5);
+        assert_eq!(edit_distance("serde", ""), 5);
+        assert_eq!(edit_distance("sered", "serde"), 1);
+        assert_eq!(edit_distance("sedre", "serde"), 1);
+        assert_eq!(edit_distance("serd", "serde"), 1);
+        assert_eq!(edit_distance("sarde", "serde"), 1);
+        assert_eq!(edit_distance("tokoi", "tokio"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn fuzzy_scores() {
+        assert!(fuzzy_score("tksrl", "tokio-serial").is_some());
+        assert!(fuzzy_score("TKSRL", "tokio_serial").is_some());
+        assert_eq!(fuzzy_score("", "tokio"), Some(0));
+        assert_eq!(fuzzy_score("lrs", "tokio-serial"), None);
+        assert_eq!(fuzzy_score("tokios", "tokio"), None);
+        // Starts of words and consecutive characters rank higher, gaps lower.
+        let mut names = ["jxsxoxn", "jason", "serde_json", "json"];
+        names.sort_by_key(|name| std::cmp::Reverse(fuzzy_score("json", name)));
+        assert_eq!(names, ["json", "serde_json", "jason", "jxsxoxn"]);
+    }
+
+    #[test]
+    fn abbreviation_scores() {
+        assert_eq!(abbreviation_score("sd", "serde_derive"), Some(0));
+        assert_eq!(abbreviation_score("tokser", "tokio-serial"), Some(0));
+        assert_eq!(abbreviation_score("SD", "serde-derive"), Some(0));
+        // Words may be left out after the first, costing one each.
+        assert_eq!(abbreviation_score("ws", "wasm-bindgen-shared"), Some(-1));
+        assert_eq!(abbreviation_score("w", "wasm-bindgen-shared"), Some(-2));
+        // The first word can't be, nor can words be taken out of order.
+        assert_eq!(abbreviation_score("d", "serde_derive"), None);
+        assert_eq!(abbreviation_score("ds", "serde_derive"), None);
+        assert_eq!(abbreviation_score("sdx", "serde_derive"), None);
+    }
+
+    #[test]
+    fn contains_scores() {
+        assert_eq!(contains_score("json", "json5", false), Some(0));
+        assert_eq!(contains_score("json", "serde_json", false), Some(-6));
+        assert_eq!(contains_score("json", "simd-json", false), Some(-5));
+        assert_eq!(contains_score("d-j", "simd_json", false), Some(-3));
+        assert_eq!(contains_score("JSON", "serde_json", false), None);
+        assert_eq!(contains_score("JSON", "serde_json", true), Some(-6));
+        assert_eq!(contains_score("yaml", "serde_json", true), None);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::cache;
+use crate::config::url_host;
+use crate::error::{Error, Result};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a request is tried before a transient failure is given up on.
+const ATTEMPTS: u32 = 4;
+/// Wait before the first retry, doubled for each one after it.
+const BACKOFF: Duration = Duration::from_millis(200);
+/// Time all attempts of a request may take together, waits included.
+const RETRY_DEADLINE: Duration = Duration::from_secs(20);
+// crates.io requires a user agent identifying the client.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// A service completions reach over the network, each of which can be turned off.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Service {
+    /// Index entries fetched from sparse registries with `--fetch`.
+    Sparse,
+    /// The crates.io web API, for `--online`, licenses and download counts.
+    Api,
+    /// Branches and tags of git dependencies listed by `git ls-remote`.
+    GitRemote,
+    /// The crates.io database dump downloaded by `import-dump`.
+    Dump,
+}
+
+impl Display for Service {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Service::Sparse => "sparse",
+            Service::Api => "api",
+            Service::GitRemote => "git-remote",
+            Service::Dump => "dump",
+        })
+    }
+}
+
+/// Whether no service may be reached, as set by [`set_offline`].
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+/// Services turned off one by one, as by [`set_disabled`].
+static DISABLED: Mutex<Vec<Service>> = Mutex::new(Vec::new());
+
+/// Reach no service at all from now on, or all that aren't disabled again.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Never reach the `disabled` services from now on, but all others again.
+pub fn set_disabled(disabled: Vec<Service>) {
+    *DISABLED.lock().unwrap() = disabled;
+}
+
+pub fn allowed(service: Service) -> bool {
+    !is_offline() && !DISABLED.lock().unwrap().contains(&service)
+}
+
+/// Fail unless `service` may be reached, guarding every network access.
+pub fn check(service: Service) -> Result<()> {
+    match allowed(service) {
+        true => Ok(()),
+        false => Err(Error::NetworkDisabled(service)),
+    }
+}
+
+/// Proxy of cargo's `http.proxy`, which takes precedence over the environment, as set by
+/// [`set_proxy`].
+static PROXY: Mutex<Option<String>> = Mutex::new(None);
+
+/// Reach every service through `proxy` from now on, or through the one the environment names.
+pub fn set_proxy(proxy: Option<String>) {
+    *PROXY.lock().unwrap() = proxy;
+}
+
+/// Whether the `no_proxy` list exempts `url` from proxies: `*`, or a host or domain optionally
+/// followed by the port `url` has to use, a domain covering its subdomains.
+fn exempts(no_proxy: &str, url: &str) -> bool {
+    let host = match url_host(url) {
+        Some(host) => host,
+        None => return false,
+    };
+    let url = url.strip_prefix("sparse+").unwrap_or(url);
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split('/').next().unwrap_or(authority);
+    let port = match authority
+        .rsplit('@')
+        .next()
+        .and_then(|host| host.split_once(':'))
+    {
+        Some((_, port)) => port.parse().ok(),
+        None if url.starts_with("http:") => Some(80),
+        None => Some(443),
+    };
+    no_proxy.split(',').map(str::trim).any(|pattern| {
+        let (pattern, pattern_port) = match pattern.rsplit_once(':') {
+            Some((pattern, pattern_port)) => (pattern, pattern_port.parse::<u16>().ok()),
+            None => (pattern, port),
+        };
+        let pattern = pattern.trim_start_matches('.');
+        pattern == "*"
+            || (port == pattern_port
+                && !pattern.is_empty()
+                && (host == pattern
+                    || host
+                        .strip_suffix(pattern)
+                        .is_some_and(|sub| sub.ends_with('.'))))
+    })
+}
+
+/// Proxy to reach `url` through: the configured one, or else the one `https_proxy`, `http_proxy`
+/// or `all_proxy` names unless `no_proxy` exempts the host, like curl does.
+fn proxy(url: &str) -> Option<String> {
+    if let Some(proxy) = &*PROXY.lock().unwrap() {
+        return Some(proxy.clone());
+    }
+    let var = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+    };
+    let exempt = var(&["no_proxy", "NO_PROXY"]).is_some_and(|no_proxy| exempts(&no_proxy, url));
+    if exempt {
+        return None;
+    }
+    match url.starts_with("https:") {
+        true => var(&["https_proxy", "HTTPS_PROXY"]),
+        // Like curl, as CGI lets requests set `HTTP_PROXY`.
+        false => var(&["http_proxy"]),
+    }
+    .or_else(|| var(&["all_proxy", "ALL_PROXY"]))
+}
+
+/// An agent reaching `url` through the proxy for it, if any.
+pub(crate) fn agent(url: &str) -> Result<ureq::Agent> {
+    let mut agent = ureq::AgentBuilder::new().user_agent(USER_AGENT);
+    if let Some(proxy) = proxy(url) {
+        debug!(%proxy, "using proxy");
+        agent = agent.proxy(ureq::Proxy::new(&proxy)?);
+    }
+    Ok(agent.build())
+}
+
+/// Fetch `url` from `service`, or `None` if it answers with one of the `missing` statuses.
+///
+/// Bodies are cached along with their `ETag` or `Last-Modified` header, so a body that didn't
+/// change since is validated with `If-None-Match` or `If-Modified-Since` rather than downloaded
+/// again.
+pub fn get(service: Service, url: &str, missing: &[u16]) -> Result<Option<String>> {
+    check(service)?;
+    let cache_name = format!("http/{}", cache::hashed(url));
+    // The validating request header on the first line, e.g. `If-None-Match: "abc"`, the body
+    // after it.
+    let cached = cache::read(&cache_name, Duration::MAX).and_then(|entry| {
+        let (header, body) = entry.split_once('\n')?;
+        let (name, value) = header.split_once(": ")?;
+        Some((name.to_string(), value.to_string(), body.to_string()))
+    });
+
+    let mut request = agent(url)?.get(url);
+    if let Some((name, value, _)) = &cached {
+        request = request.set(name, value);
+    }
+    match call(request, missing)? {
+        Some(resp) if resp.status() == 304 => {
+            debug!(%url, "cached response is current");
+            let (_, _, body) =
+                cached.ok_or_else(|| Error::Response("304 to an uncached request".to_string()))?;
+            Ok(Some(body))
+        }
+        Some(resp) => {
+            let validator = match (resp.header("ETag"), resp.header("Last-Modified")) {
+                (Some(etag), _) => Some(format!("If-None-Match: {}", etag)),
+                (None, Some(date)) => Some(format!("If-Modified-Since: {}", date)),
+                (None, None) => None,
+            };
+            let body = resp.into_string().map_err(Error::ResponseBody)?;
+            if let Some(validator) = validator.filter(|validator| !validator.contains('\n')) {
+                cache::write(&cache_name, &format!("{}\n{}", validator, body));
+            }
+            Ok(Some(body))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Whether a request failing with `err` may succeed when tried again.
+fn transient(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(status, _) => matches!(status, 408 | 429 | 500..=599),
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+/// Somewhere between half of `backoff` and all of it, so clients failing together don't retry
+/// together.
+fn jitter(backoff: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    backoff / 2 + backoff.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+/// Send `request`, retrying transient failures with exponential backoff until [`ATTEMPTS`] tries
+/// or [`RETRY_DEADLINE`] are used up. `None` if it answers with one of the `missing` statuses.
+fn call(request: ureq::Request, missing: &[u16]) -> Result<Option<ureq::Response>> {
+    let deadline = Instant::now() + RETRY_DEADLINE;
+    let mut backoff = BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let timeout = TIMEOUT.min(deadline.saturating_duration_since(Instant::now()));
+        let err = match request.clone().timeout(timeout).call() {
+            Ok(resp) => return Ok(Some(resp)),
+            Err(ureq::Error::Status(status, _)) if missing.contains(&status) => return Ok(None),
+            Err(err) => err,
+        };
+        let wait = jitter(backoff);
+        if !transient(&err) || attempt >= ATTEMPTS || Instant::now() + wait >= deadline {
+            return Err(err.into());
+        }
+        debug!(url = request.url(), attempt, ?wait, error = %err, "retrying request");
+        thread::sleep(wait);
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_proxy_exemptions() {
+        let url = "https://index.crates.io/se/rd/serde";
+        assert!(exempts("index.crates.io", url));
+        assert!(exempts("example.com, crates.io", url));
+        assert!(exempts(".crates.io", url));
+        assert!(exempts("*", url));
+        assert!(exempts("index.crates.io:443", url));
+        assert!(exempts("localhost:8080", "http://localhost:8080/index"));
+        assert!(!exempts("", url));
+        assert!(!exempts("s.io", url));
+        assert!(!exempts("index.crates.io:8080", url));
+        assert!(!exempts("localhost:443", "http://localhost/index"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use tokio::task::{self, JoinError};
+
+use crate::backend::IndexBackend;
+use crate::error::{Error, Result};
+use crate::{
+    complete_crate_described, complete_feature_described, describe_crate, ranked_crate_names,
+    versions, Candidate, Options,
+};
+
+/// How many crates to look up at once, e.g. over HTTP for sparse registries.
+const CONCURRENCY: usize = 16;
+
+/// An index shared by the tasks looking up crates.
+pub type SharedIndex = Arc<dyn IndexBackend + Send + Sync>;
+
+/// The result of a task, failing if it panicked.
+fn joined<T>(result: Result<T, JoinError>) -> Result<T> {
+    result.map_err(Error::from)
+}
+
+/// Like [`complete_crate_described`], but running on tokio's blocking threads so it never blocks
+/// the runtime, and looking up the versions of several crates at once.
+pub async fn complete_crate_async(
+    index: SharedIndex,
+    partial_command: String,
+    options: Options,
+) -> Result<Vec<Candidate>> {
+    let options = Arc::new(options);
+    if partial_command.contains('@') {
+        return joined(
+            task::spawn_blocking(move || {
+                complete_crate_described(&*index, &partial_command, &options)
+            })
+            .await,
+        )?;
+    }
+    let names = {
+        let (index, options) = (index.clone(), options.clone());
+        joined(
+            task::spawn_blocking(move || ranked_crate_names(&*index, &partial_command, &options))
+                .await,
+        )??
+    };
+    let limit = options.limit.map_or(usize::MAX, |limit| limit + 1);
+    let mut names = names.into_iter();
+    let mut lookups = VecDeque::new();
+    let mut candidates = vec![];
+    while candidates.len() < limit {
+        // Describe crates in the order they rank, while the next ones are looked up.
+        while lookups.len() < CONCURRENCY && !options.expired() {
+            let (name, score) = match names.next() {
+                Some(name) => name,
+                None => break,
+            };
+            let index = index.clone();
+            lookups.push_back(task::spawn_blocking(move || {
+                let versions = versions(&*index, &name).ok().flatten().unwrap_or_default();
+                let registry = index.registry(&name);
+                (name, score, versions, registry)
+            }));
+        }
+        let (name, score, versions, registry) = match lookups.pop_front() {
+            Some(lookup) => joined(lookup.await)?,
+            None => break,
+        };
+        candidates.extend(describe_crate(name, score, versions, registry, &options));
+    }
+    Ok(candidates)
+}
+
+/// Like [`complete_feature_described`], but running on tokio's blocking threads so it never
+/// blocks the runtime.
+pub async fn complete_feature_async(
+    index: SharedIndex,
+    crate_name: String,
+    version: String,
+    partial: String,
+) -> Result<Vec<Candidate>> {
+    joined(
+        task::spawn_blocking(move || {
+            complete_feature_described(&*index, &crate_name, &version, &partial)
+        })
+        .await,
+    )?
+}
@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use fst::{Automaton, IntoStreamer, Set};
+use itertools::Itertools;
+use tracing::debug;
+
+use crate::backend::IndexBackend;
+use crate::cache::{cache_dir, hashed};
+use crate::crates::{is_crate_name, parse_lines, Crate};
+use crate::deadline::Deadline;
+use crate::dump::Dump;
+use crate::error::{Error, Result};
+use crate::{api, popularity};
+
+/// How many of the most downloaded crates [`warm`] caches the versions of.
+const POPULAR: usize = 1000;
+
+/// Directory the caches of the index identified by `key` are kept in.
+fn dir(key: &str) -> Option<PathBuf> {
+    Some(cache_dir()?.join("index").join(hashed(key)))
+}
+
+/// Write a file at once, so readers never see half of it.
+fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    let write = || {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(tmp, path)
+    };
+    write().map_err(|source| Error::FileWrite {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Matches names starting with a prefix, treating `-` and `_` alike.
+struct Prefix<'a>(&'a [u8]);
+
+impl Automaton for Prefix<'_> {
+    /// How much of the prefix matched so far, or `None` once it can't match anymore.
+    type State = Option<usize>;
+
+    fn start(&self) -> Self::State {
+        Some(0)
+    }
+    fn is_match(&self, state: &Self::State) -> bool {
+        *state == Some(self.0.len())
+    }
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        self.is_match(state)
+    }
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let matched = (*state)?;
+        match self.0.get(matched) {
+            None => Some(matched),
+            Some(&expected) if expected == byte => Some(matched + 1),
+            Some(b'-' | b'_') if byte == b'-' || byte == b'_' => Some(matched + 1),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Answers from the caches [`warm`] wrote for an index where it can, and from the index itself
+/// otherwise.
+///
+/// The caches are rebuilt first if the [state](IndexBackend::state) of the index changed since
+/// they were written.
+pub struct PersistentIndex {
+    inner: Box<dyn IndexBackend>,
+    /// The cache directory, if the caches in it are up to date.
+    dir: OnceLock<Option<PathBuf>>,
+    key: String,
+    /// All crate names as a finite state transducer, which answers prefix queries without
+    /// reading every name.
+    names: OnceLock<Option<Set<Vec<u8>>>>,
+}
+
+impl PersistentIndex {
+    /// Wrap an index, `key` telling it apart from other indexes.
+    pub fn new(inner: Box<dyn IndexBackend>, key: &str) -> Self {
+        Self {
+            inner,
+            dir: OnceLock::new(),
+            key: key.to_string(),
+            names: OnceLock::new(),
+        }
+    }
+
+    fn dir(&self) -> Option<&Path> {
+        self.dir
+            .get_or_init(|| {
+                let dir = dir(&self.key)?;
+                // Never warmed.
+                let written = fs::read_to_string(dir.join("state")).ok()?;
+                let state = self.inner.state().unwrap_or_default();
+                if written != state {
+                    debug!(dir = %dir.display(), "rebuilding caches of changed index");
+                    build(&*self.inner, &dir, cached_versions(&dir)).ok()?;
+                }
+                Some(dir)
+            })
+            .as_deref()
+    }
+
+    fn names(&self) -> Option<&Set<Vec<u8>>> {
+        self.names
+            .get_or_init(|| {
+                let content = fs::read(self.dir()?.join("names.fst")).ok()?;
+                Set::new(content).ok()
+            })
+            .as_ref()
+    }
+}
+
+impl IndexBackend for PersistentIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        match self.names() {
+            Some(names) => Ok(names
+                .search(Prefix(prefix.as_bytes()))
+                .into_stream()
+                .into_strs()?),
+            None => self.inner.crates_with_prefix(prefix),
+        }
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        match self.names() {
+            Some(_) => self.crates_with_prefix(prefix),
+            None => self.inner.crates_with_prefix_until(prefix, deadline),
+        }
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        // A typed name is joined to the cache directory, so it must not lead out of it.
+        let cached = self
+            .dir()
+            .filter(|_| is_crate_name(name))
+            .and_then(|dir| fs::read_to_string(dir.join("versions").join(name)).ok());
+        match cached {
+            Some(lines) => Ok(Some(parse_lines(&lines)?)),
+            None => self.inner.versions(name),
+        }
+    }
+    fn state(&self) -> Option<String> {
+        self.inner.state()
+    }
+    fn registry(&self, name: &str) -> Option<String> {
+        self.inner.registry(name)
+    }
+}
+
+/// The most downloaded crates, by the imported dump if there is one and by crates.io otherwise.
+fn popular(dump: Option<&Dump>) -> Vec<String> {
+    let mut downloads: Vec<(String, u64)> = popularity::downloads("", dump).into_iter().collect();
+    if downloads.is_empty() {
+        downloads = api::downloads("").unwrap_or_default();
+    }
+    downloads.sort_by_key(|(_, downloads)| Reverse(*downloads));
+    downloads
+        .into_iter()
+        .take(POPULAR)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Crates whose versions were cached before.
+fn cached_versions(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir.join("versions"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.ends_with(".tmp"))
+        .collect()
+}
+
+/// Cache the names of all crates of `index` into `dir` and the versions of the given crates,
+/// along with the state of the index they reflect.
+fn build(index: &dyn IndexBackend, dir: &Path, crates: Vec<String>) -> Result<(usize, usize)> {
+    let state = index.state().unwrap_or_default();
+    let names: Vec<String> = index
+        .crates_with_prefix("")?
+        .into_iter()
+        .sorted()
+        .dedup()
+        .collect();
+    let mut cached = 0;
+    for name in crates.into_iter().filter(|name| is_crate_name(name)) {
+        let path = dir.join("versions").join(&name);
+        match index.versions(&name)? {
+            Some(versions) => {
+                let lines: String = versions
+                    .into_iter()
+                    .map(|version| Ok(serde_json::to_string(&version)? + "\n"))
+                    .collect::<Result<_>>()?;
+                write_atomic(&path, &lines)?;
+                cached += 1;
+            }
+            // Gone from the index since it was cached.
+            None => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+    write_atomic(
+        &dir.join("names.fst"),
+        Set::from_iter(&names)?.as_fst().as_bytes(),
+    )?;
+    write_atomic(&dir.join("state"), state)?;
+    Ok((names.len(), cached))
+}
+
+/// Whether the caches [`warm`] wrote for an index no longer reflect it, `None` if it was never
+/// warmed.
+pub fn stale(index: &dyn IndexBackend, key: &str) -> Option<bool> {
+    let written = fs::read_to_string(dir(key)?.join("state")).ok()?;
+    Some(written != index.state().unwrap_or_default())
+}
+
+/// Cache the names of all crates of `index` and the versions of the most downloaded ones, so
+/// completions need not walk the index. Returns how many names and crates were cached.
+pub fn warm(index: &dyn IndexBackend, key: &str, dump: Option<&Dump>) -> Result<(usize, usize)> {
+    let dir = dir(key).ok_or(Error::NoHome)?;
+    build(index, &dir, popular(dump))
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::api;
+use crate::cache;
+use crate::dump::Dump;
+
+/// Download counts change slowly, so a day old ranking is as good as a fresh one.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// All-time download counts of popular crates matching a partial name, by crate name.
+///
+/// Counts come from the imported database dump if there is one, and from crates.io otherwise.
+/// Crates missing from the result are too obscure to rank and count as never downloaded.
+pub fn downloads(partial_name: &str, dump: Option<&Dump>) -> HashMap<String, u64> {
+    if let Some(dump) = dump {
+        if let Ok(downloads) = dump.downloads(partial_name) {
+            return downloads;
+        }
+    }
+    if partial_name.is_empty() {
+        return HashMap::new();
+    }
+    // Escaped like in the query, as typed text can hold `/` or `..`.
+    let cache_name = format!("downloads/{}", api::encode(partial_name));
+    let content = match cache::read(&cache_name, CACHE_TTL) {
+        Some(content) => content,
+        None => match api::downloads(partial_name) {
+            Ok(counts) => {
+                let content = counts
+                    .iter()
+                    .map(|(name, count)| format!("{}\t{}\n", name, count))
+                    .collect::<String>();
+                cache::write(&cache_name, &content);
+                content
+            }
+            Err(_) => return HashMap::new(),
+        },
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let (name, count) = line.split_once('\t')?;
+            Some((name.to_string(), count.parse().ok()?))
+        })
+        .collect()
+}
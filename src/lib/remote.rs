@@ -0,0 +1,124 @@
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::net::{self, Service};
+use crate::{cache, Candidate, Kind};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// A ref advertised by a git remote.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Ref {
+    pub name: String,
+    pub oid: String,
+}
+
+/// Refs of a git remote, as listed by `git ls-remote` and cached for a while.
+pub fn ls_remote(url: &str) -> Result<Vec<Ref>> {
+    let cache_name = format!("ls-remote/{}", cache::hashed(url));
+    let output = match cache::read(&cache_name, CACHE_TTL) {
+        Some(output) => output,
+        None => {
+            let output = run_ls_remote(url)?;
+            cache::write(&cache_name, &output);
+            output
+        }
+    };
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let (oid, name) = line.split_once('\t')?;
+            Some(Ref {
+                name: name.to_string(),
+                oid: oid.to_string(),
+            })
+        })
+        .collect())
+}
+
+fn run_ls_remote(url: &str) -> Result<String> {
+    net::check(Service::GitRemote)?;
+    let mut child = Command::new("git")
+        .args(["ls-remote", "--", url])
+        // Never block on credential prompts.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(Error::GitSpawn)?;
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(Error::GitSpawn)? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::GitRemote {
+                url: url.to_string(),
+                reason: "timed out".to_string(),
+            });
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    if !status.success() {
+        return Err(Error::GitRemote {
+            url: url.to_string(),
+            reason: format!("failed: {}", status),
+        });
+    }
+    let failed = || Error::GitRemote {
+        url: url.to_string(),
+        reason: "output can't be read".to_string(),
+    };
+    reader.join().map_err(|_| failed())?.map_err(|_| failed())
+}
+
+/// Complete the value of `--branch`, `--tag` or `--rev` from the refs of a git remote.
+pub fn complete_ref(url: &str, flag: &str) -> Result<Vec<Candidate>> {
+    let refs = ls_remote(url)?;
+    let named = |namespace: &str| -> Vec<Candidate> {
+        refs.iter()
+            .filter_map(|ref_| {
+                let name = ref_.name.strip_prefix(namespace)?;
+                // Peeled annotated tags duplicate the tag itself.
+                (!name.ends_with("^{}")).then(|| Candidate {
+                    value: name.to_string(),
+                    display: None,
+                    description: Some(ref_.oid[..ref_.oid.len().min(10)].to_string()),
+                    score: None,
+                    kind: Kind::Value,
+                    yanked: false,
+                })
+            })
+            .collect()
+    };
+    Ok(match flag {
+        "--branch" => named("refs/heads/"),
+        "--tag" => named("refs/tags/"),
+        "--rev" => refs
+            .iter()
+            .filter(|ref_| !ref_.name.ends_with("^{}"))
+            .map(|ref_| Candidate {
+                value: ref_.oid.clone(),
+                display: None,
+                description: Some(ref_.name.clone()),
+                score: None,
+                kind: Kind::Value,
+                yanked: false,
+            })
+            .collect(),
+        _ => vec![],
+    })
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use tracing::debug;
+
+use crate::backend::IndexBackend;
+use crate::crates::{entry_path, parse_lines, Crate, CratesIndex};
+use crate::deadline::Deadline;
+use crate::error::Result;
+use crate::net::{self, Service};
+
+/// Index of a sparse registry: cargo's local cache, optionally fetching missing entries over HTTP.
+pub struct SparseIndex {
+    cache: CratesIndex,
+    /// Base url of the registry to fetch entries from.
+    remote: Option<String>,
+}
+
+impl SparseIndex {
+    pub fn new(path: &Path, remote: Option<String>) -> Self {
+        Self {
+            cache: CratesIndex::new(path.join(".cache")),
+            remote,
+        }
+    }
+}
+
+impl IndexBackend for SparseIndex {
+    fn crates_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        IndexBackend::crates_with_prefix(&self.cache, prefix)
+    }
+    fn crates_with_prefix_until(
+        &self,
+        prefix: &str,
+        deadline: Option<&Deadline>,
+    ) -> Result<Vec<String>> {
+        self.cache.crates_with_prefix_until(prefix, deadline)
+    }
+    fn versions(&self, name: &str) -> Result<Option<Vec<Crate>>> {
+        if let Some(versions) = self.cache.versions(name)? {
+            return Ok(Some(versions));
+        }
+        match &self.remote {
+            Some(remote) => Ok(fetch(remote, name)?
+                .map(|lines| parse_lines(&lines))
+                .transpose()?),
+            None => Ok(None),
+        }
+    }
+    fn state(&self) -> Option<String> {
+        self.cache.state()
+    }
+}
+
+/// Guess the base url of a sparse registry from its index directory name.
+///
+/// Cargo names sparse index directories `<host>-<hash>`, e.g. `index.crates.io-6f17d22bba15001f`.
+pub fn index_url(path: &Path) -> Option<String> {
+    let dir_name = path.file_name()?.to_str()?;
+    let (host, _hash) = dir_name.rsplit_once('-')?;
+    Some(format!("https://{}/", host))
+}
+
+/// Fetch the index entry of a crate. Returns `None` if the registry doesn't know the crate.
+pub fn fetch(index_url: &str, name: &str) -> Result<Option<String>> {
+    let url = format!("{}{}", index_url, entry_path(name));
+    debug!(%url, "fetching index entry");
+    net::get(Service::Sparse, &url, &[403, 404, 410, 451])
+}
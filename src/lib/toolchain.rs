@@ -0,0 +1,42 @@
+use std::env;
+use std::process::Command;
+
+use semver::{Prerelease, Version};
+
+/// Version of the active `rustc`, honoring `RUSTC` and rustup's toolchain overrides.
+///
+/// Nightly and beta toolchains count as the release they lead up to.
+pub fn rustc_version() -> Option<Version> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = Command::new(rustc).arg("-V").output().ok()?;
+    // e.g. `rustc 1.75.0 (82e1608df 2023-12-21)`
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let mut version = Version::parse(stdout.split_whitespace().nth(1)?).ok()?;
+    version.pre = Prerelease::EMPTY;
+    Some(version)
+}
+
+/// Parse a `rust-version` like `1.70`, which may leave out the minor and patch parts.
+pub fn parse_rust_version(rust_version: &str) -> Option<Version> {
+    let mut parts = rust_version.trim().split('.').map(str::parse::<u64>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().transpose().ok()?.unwrap_or(0);
+    let patch = parts.next().transpose().ok()?.unwrap_or(0);
+    Some(Version::new(major, minor, patch))
+}
+
+/// Whether a crate version needs an edition newer than `max_edition`.
+///
+/// The index doesn't record editions, so versions whose `edition` is unknown are assumed not to;
+/// a `rust-version` tells which releases a crate builds with, not which edition it uses.
+pub fn requires_newer_edition(edition: Option<&str>, max_edition: &str) -> bool {
+    edition.is_some_and(|edition| edition > max_edition)
+}
+
+/// Whether `rustc` is recent enough for a crate declaring `rust_version`; crates declaring none or
+/// an unreadable one are assumed to build.
+pub fn supports(rustc: &Version, rust_version: Option<&str>) -> bool {
+    rust_version
+        .and_then(parse_rust_version)
+        .is_none_or(|required| required <= *rustc)
+}
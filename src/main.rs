@@ -1,15 +1,240 @@
-use anyhow::Result;
-use clap::Parser;
-use itertools::Itertools;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, IsTerminal};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
 
-use cargo_edit_completion_lib::crates::CratesIndex;
-use cargo_edit_completion_lib::{complete_crate, complete_feature};
+use anyhow::{anyhow, Context, Result};
+use clap::{AppSettings, ArgEnum, Parser};
+use serde::Deserialize;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+use cargo_edit_completion_lib::advisory::{default_db, AdvisoryDb};
+use cargo_edit_completion_lib::backend::{self, IndexBackend, MultiIndex};
+use cargo_edit_completion_lib::cache;
+use cargo_edit_completion_lib::config::CargoConfig;
+use cargo_edit_completion_lib::crates;
+use cargo_edit_completion_lib::deadline::Deadline;
+use cargo_edit_completion_lib::dump::{self, complete_full_text, complete_search, Dump};
+use cargo_edit_completion_lib::error::Error;
+use cargo_edit_completion_lib::filter::CrateFilter;
+use cargo_edit_completion_lib::history::{self, History};
+use cargo_edit_completion_lib::license::LicensePolicy;
+use cargo_edit_completion_lib::line::{complete_flag, complete_line, cut_at, split_words};
+use cargo_edit_completion_lib::manifest::{
+    complete_enabled_features, complete_removable, complete_upgradable, find_manifest,
+    package_edition, DepKind,
+};
+use cargo_edit_completion_lib::matching::{CaseSensitivity, MatchMode};
+use cargo_edit_completion_lib::net::{self, Service};
+use cargo_edit_completion_lib::persist::{self, PersistentIndex};
+use cargo_edit_completion_lib::toolchain::rustc_version;
+use cargo_edit_completion_lib::{
+    complete_crate_described_iter, complete_feature_ranked, Candidate, Options, Weights,
+};
+
+use crate::install::Shell;
+use crate::output::Format;
+use crate::settings::{Settings, CACHE_DIR_VAR};
+
+mod bench;
+mod daemon;
+mod doctor;
+mod install;
+mod output;
+mod serve;
+mod settings;
+mod stats;
 
 #[derive(Parser)]
 #[clap(version = "1.0", author = "LightQuantum <self@lightquantum.me>")]
 struct Opts {
+    /// Tell nothing on stderr when completing fails, only by the exit code
+    #[clap(long, short, env = "CARGO_EDIT_COMPLETION_QUIET")]
+    quiet: bool,
+    /// Log what is done to stderr; repeat for more detail, or filter like `RUST_LOG` does. Set
+    /// `CARGO_EDIT_COMPLETION_VERBOSE` to how often it is repeated instead
+    #[clap(long, short, parse(from_occurrences))]
+    verbose: u64,
+    /// Settings file to read defaults of the options from instead of
+    /// `$XDG_CONFIG_HOME/cargo-edit-completion/config.toml`
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_CONFIG")]
+    config: Option<PathBuf>,
+    /// Fetch index entries from sparse registries over HTTP
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_FETCH")]
+    fetch: bool,
+    /// Query the crates.io API for crates missing from the local index
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_ONLINE")]
+    online: bool,
+    /// Never access the network, like `CARGO_NET_OFFLINE=true` tells cargo, overriding `--fetch`
+    /// and `--online`
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_OFFLINE")]
+    offline: bool,
+    /// Registry index directory to complete from, skipping auto-detection
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_INDEX", conflicts_with_all = &["registry", "all-registries"])]
+    index_path: Option<PathBuf>,
+    /// Complete from a registry configured in .cargo/config.toml instead of the default one
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_REGISTRY")]
+    registry: Option<String>,
+    /// Merge the indexes of all registries cargo has fetched, describing crates with their registry
+    #[clap(
+        long,
+        conflicts_with = "registry",
+        env = "CARGO_EDIT_COMPLETION_ALL_REGISTRIES"
+    )]
+    all_registries: bool,
+    /// Complete prerelease versions even when the typed version has no prerelease part
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_INCLUDE_PRERELEASE")]
+    include_prerelease: bool,
+    /// Complete yanked versions too, marking them as such
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_INCLUDE_YANKED")]
+    include_yanked: bool,
+    /// Leave out crates whose every version is yanked instead of marking them
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_HIDE_YANKED_CRATES")]
+    hide_yanked_crates: bool,
+    /// Complete every version instead of only the newest patch of each minor version
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_ALL_VERSIONS")]
+    all_versions: bool,
+    /// Check versions against the `rust-version` they declare and the active toolchain, listing
+    /// the ones too new last or hiding them
+    #[clap(long, arg_enum, env = "CARGO_EDIT_COMPLETION_MSRV")]
+    msrv: Option<Msrv>,
+    /// Leave out versions known to need an edition newer than the given one, or than the one of the
+    /// current package if none is given
+    #[clap(long, require_equals = true, env = "CARGO_EDIT_COMPLETION_MAX_EDITION")]
+    max_edition: Option<Option<String>>,
+    /// Mark versions affected by RustSec advisories
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_ADVISORIES")]
+    advisories: bool,
+    /// Leave out versions affected by RustSec advisories
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_NO_VULNERABLE")]
+    no_vulnerable: bool,
+    /// Advisory database checkout to use instead of the one of `cargo audit`
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_ADVISORY_DB")]
+    advisory_db: Option<PathBuf>,
+    /// Only complete crates and versions under licenses the given SPDX expression names, e.g.
+    /// "MIT OR Apache-2.0", looking licenses up on crates.io when the index lacks them and marking
+    /// those it can't find
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_LICENSE_ALLOW")]
+    license_allow: Option<String>,
+    /// List the most downloaded crates first, ranking them by their download count on crates.io
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_RANK_BY_DOWNLOADS")]
+    rank_by_downloads: bool,
+    /// How typed text selects crate names, `prefix` by default
+    #[clap(long = "match", arg_enum, env = "CARGO_EDIT_COMPLETION_MATCH")]
+    match_mode: Option<Match>,
+    /// Whether prefixes match crate names regardless of case, `sensitive` by default
+    #[clap(long, arg_enum, env = "CARGO_EDIT_COMPLETION_CASE")]
+    case: Option<Case>,
+    /// Don't list crates and features picked before first
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_NO_HISTORY")]
+    no_history: bool,
+    /// Output no more than this many candidates, the best ranked ones
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_LIMIT")]
+    limit: Option<usize>,
+    /// Stop searching after this many milliseconds, completing the candidates found so far
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_TIMEOUT_MS")]
+    timeout_ms: Option<u64>,
+    /// Walk the index with no more than this many threads, one per CPU by default
+    #[clap(long, short = 'j', env = "CARGO_EDIT_COMPLETION_JOBS")]
+    jobs: Option<usize>,
+    /// End candidates with a NUL instead of a newline, e.g. for `xargs -0` or `fzf --read0`
+    #[clap(long, short = '0', env = "CARGO_EDIT_COMPLETION_PRINT0")]
+    print0: bool,
+    /// Output format of the candidates
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "plain",
+        env = "CARGO_EDIT_COMPLETION_FORMAT"
+    )]
+    format: Format,
+    /// Answer queries read from stdin, one per line like `crate tok` or `feature serde@1 der`,
+    /// each answer ending with an empty line in line-oriented formats
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_STDIN")]
+    stdin: bool,
+    /// Complete without asking a running daemon
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_NO_DAEMON")]
+    no_daemon: bool,
+    /// Socket of the daemon, e.g. `$XDG_RUNTIME_DIR/cargo-edit-completion.sock`, or its named
+    /// pipe on Windows
+    #[clap(long, env = "CARGO_EDIT_COMPLETION_SOCKET")]
+    socket: Option<PathBuf>,
     #[clap(subcommand)]
-    mode: Mode,
+    mode: Option<Mode>,
+    // Only read from the settings.
+    #[clap(skip)]
+    disabled: Vec<Service>,
+    #[clap(skip)]
+    cache_dir: Option<PathBuf>,
+    #[clap(skip)]
+    deny: Vec<String>,
+    #[clap(skip)]
+    allow: Vec<String>,
+    #[clap(skip)]
+    boost_prefixes: Vec<String>,
+    #[clap(skip)]
+    weights: Option<Weights>,
+}
+
+#[derive(ArgEnum, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Match {
+    /// Names starting with the text
+    Prefix,
+    /// Names containing the characters of the text in order, best matches first
+    Fuzzy,
+    /// Names whose words start with the parts of the text, e.g. `sd` for `serde_derive`
+    Abbreviation,
+    /// Names containing the text anywhere, e.g. `json` for `serde_json`
+    Contains,
+    /// Names matching the text as a regular expression, e.g. `^tokio-.*-compat$`
+    Regex,
+}
+
+impl From<Match> for MatchMode {
+    fn from(mode: Match) -> Self {
+        match mode {
+            Match::Prefix => MatchMode::Prefix,
+            Match::Fuzzy => MatchMode::Fuzzy,
+            Match::Abbreviation => MatchMode::Abbreviation,
+            Match::Contains => MatchMode::Contains,
+            Match::Regex => MatchMode::Regex,
+        }
+    }
+}
+
+#[derive(ArgEnum, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Case {
+    /// Match case exactly
+    Sensitive,
+    /// Ignore case
+    Insensitive,
+    /// Ignore case unless the text has an uppercase letter
+    Smart,
+}
+
+impl From<Case> for CaseSensitivity {
+    fn from(case: Case) -> Self {
+        match case {
+            Case::Sensitive => CaseSensitivity::Sensitive,
+            Case::Insensitive => CaseSensitivity::Insensitive,
+            Case::Smart => CaseSensitivity::Smart,
+        }
+    }
+}
+
+#[derive(ArgEnum, Debug, Copy, Clone, Eq, PartialEq)]
+enum Msrv {
+    /// List versions too new for the toolchain last
+    Demote,
+    /// Leave out versions too new for the toolchain
+    Hide,
 }
 
 #[derive(Parser)]
@@ -17,10 +242,167 @@ struct WrappedString {
     input: String,
 }
 
+#[derive(Parser)]
+struct Feature {
+    /// `name[@version][:partial]`
+    input: String,
+    /// The partial feature, instead of giving it after `:`
+    partial: Option<String>,
+}
+
 #[derive(Parser)]
 enum Mode {
     Crate(WrappedString),
-    Feature(WrappedString),
+    Feature(Feature),
+    /// Complete a flag of `cargo add`, `rm` or `upgrade`
+    Flags(Flags),
+    /// Complete a dependency of the current manifest for `cargo rm`
+    Remove(Remove),
+    /// Complete a `name@version` spec of a workspace dependency for `cargo upgrade`
+    Upgrade(Upgrade),
+    /// Complete a feature the current manifest enables for a dependency
+    EnabledFeatures(EnabledFeatures),
+    /// Complete the word under the cursor of a full cargo-edit command line
+    Line(Line),
+    /// Complete the word under the cursor of `COMP_LINE`/`COMP_POINT` for bash
+    Bash,
+    /// Install completion scripts for cargo add, rm and upgrade
+    InstallCompletions(InstallCompletions),
+    /// Complete a crate name by keyword or category, or search crate descriptions for words, using
+    /// the imported database dump
+    Search(Search),
+    /// Remember the crates and features an executed `cargo add` command line picked, to list them
+    /// first from then on
+    Record(Record),
+    /// Import the crates.io database dump to rank and describe crates with offline
+    ImportDump(ImportDump),
+    /// Answer JSON-lines requests of an editor, keeping the process alive between them
+    Serve(Serve),
+    /// Keep indexes in memory and answer completions over a Unix socket or named pipe, which other
+    /// invocations use when it is up
+    Daemon,
+    /// Cache the names of all crates and the versions of the most downloaded ones, so the first
+    /// completions need not walk the index
+    Warm,
+    /// Time representative completions against the index, cold and warm, printing percentiles
+    Bench(Bench),
+    /// Tell the registries, how many crates their indexes hold and how large they and the caches
+    /// are
+    Stats,
+    /// Check the registry index, caches and shell integration, telling how to fix problems
+    Doctor,
+}
+
+#[derive(Parser)]
+struct Line {
+    /// The command line, e.g. `cargo add serde --features de`
+    #[clap(long)]
+    line: String,
+    /// Cursor offset in characters, defaulting to the end of the line
+    #[clap(long)]
+    point: Option<usize>,
+}
+
+#[derive(Parser)]
+#[clap(setting = AppSettings::AllowLeadingHyphen)]
+struct Flags {
+    /// The subcommand, e.g. `add`
+    subcommand: String,
+    /// The partial flag, e.g. `--re`
+    #[clap(default_value = "")]
+    input: String,
+}
+
+#[derive(Parser)]
+struct Remove {
+    #[clap(default_value = "")]
+    input: String,
+    /// Complete development dependencies
+    #[clap(long, short = 'D', conflicts_with = "build")]
+    dev: bool,
+    /// Complete build dependencies
+    #[clap(long, short = 'B')]
+    build: bool,
+    /// Complete dependencies of the given target platform
+    #[clap(long)]
+    target: Option<String>,
+    /// Manifest to read instead of the one of the current directory
+    #[clap(long)]
+    manifest_path: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct Upgrade {
+    #[clap(default_value = "")]
+    input: String,
+    /// Annotate the specs with the latest version in the index
+    #[clap(long)]
+    latest: bool,
+    /// Manifest to read instead of the one of the current directory
+    #[clap(long)]
+    manifest_path: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct EnabledFeatures {
+    /// Name of the dependency in the manifest
+    dependency: String,
+    #[clap(default_value = "")]
+    input: String,
+    /// Manifest to read instead of the one of the current directory
+    #[clap(long)]
+    manifest_path: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct InstallCompletions {
+    #[clap(arg_enum)]
+    shell: Shell,
+    /// Print the scripts instead of writing them to the shell's completion directory
+    #[clap(long)]
+    print: bool,
+}
+
+#[derive(Parser)]
+struct Search {
+    /// The partial crate name, or words to search for if neither a keyword nor category is given
+    #[clap(default_value = "")]
+    input: String,
+    /// Complete crates with the given keyword, e.g. `async`
+    #[clap(long)]
+    keyword: Option<String>,
+    /// Complete crates in the given category or its subcategories, e.g. `command-line-utilities`
+    #[clap(long)]
+    category: Option<String>,
+}
+
+#[derive(Parser)]
+struct Record {
+    /// The command line, e.g. `cargo add serde --features derive`
+    #[clap(long)]
+    line: String,
+}
+
+#[derive(Parser)]
+struct ImportDump {
+    /// A downloaded `db-dump.tar.gz` to import instead of fetching the latest one
+    archive: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct Bench {
+    /// How many times to complete each query
+    #[clap(long, default_value = "10")]
+    runs: usize,
+    /// Queries to time instead of the default ones, e.g. `crate tok` or `feature serde@1 der`
+    queries: Vec<String>,
+}
+
+#[derive(Parser)]
+struct Serve {
+    /// Read requests from stdin and write responses to stdout
+    #[clap(long)]
+    stdio: bool,
 }
 
 #[derive(Parser)]
@@ -28,21 +410,457 @@ struct Crate {
     input: String,
 }
 
-fn entry() -> Result<()> {
-    let opts = Opts::try_parse()?;
-    let index = CratesIndex::default();
+/// How to print candidates, taken from the global options before a mode consumes them.
+struct OutputOptions {
+    format: Format,
+    limit: Option<usize>,
+    print0: bool,
+    /// Tells whether the search was cut short, the same one the completion options carry.
+    deadline: Option<Deadline>,
+}
+
+/// Print candidates as they come, keeping only the first `limit` ones.
+fn print(output: &OutputOptions, candidates: impl Iterator<Item = Candidate>) -> Result<()> {
+    let terminator = if output.print0 { '\0' } else { '\n' };
+    Ok(output::write(
+        output.format,
+        &mut io::stdout().lock(),
+        candidates,
+        output.limit,
+        output.deadline.as_ref(),
+        terminator,
+    )?)
+}
+
+/// The manifest `--manifest-path` names, or else the one of the directory `cwd`.
+fn manifest_or_current(manifest_path: &Option<PathBuf>, cwd: &Path) -> Result<PathBuf> {
+    match manifest_path {
+        Some(path) => Ok(cwd.join(path)),
+        None => find_manifest(cwd)
+            .ok_or_else(|| anyhow!("could not find Cargo.toml in the current directory")),
+    }
+}
+
+/// Complete a mode needing no index, or `None` for the others.
+fn complete_standalone(mode: &Mode) -> Option<Result<Vec<Candidate>>> {
+    Some(match mode {
+        Mode::Search(args) => Dump::open_default()
+            .ok_or_else(|| anyhow!("no database dump, run `import-dump` first"))
+            .and_then(|dump| {
+                Ok(if args.keyword.is_none() && args.category.is_none() {
+                    complete_full_text(&dump, &args.input)?
+                } else {
+                    complete_search(
+                        &dump,
+                        args.keyword.as_deref(),
+                        args.category.as_deref(),
+                        &args.input,
+                    )?
+                })
+            }),
+        Mode::Flags(args) => Ok(complete_flag(&args.subcommand, &[], &args.input)),
+        Mode::Remove(args) => {
+            let kind = match (args.dev, args.build) {
+                (true, _) => DepKind::Dev,
+                (_, true) => DepKind::Build,
+                _ => DepKind::Normal,
+            };
+            env::current_dir()
+                .map_err(anyhow::Error::from)
+                .and_then(|cwd| manifest_or_current(&args.manifest_path, &cwd))
+                .and_then(|manifest| {
+                    Ok(complete_removable(
+                        &manifest,
+                        kind,
+                        args.target.as_deref(),
+                        &args.input,
+                    )?)
+                })
+        }
+        Mode::EnabledFeatures(args) => env::current_dir()
+            .map_err(anyhow::Error::from)
+            .and_then(|cwd| manifest_or_current(&args.manifest_path, &cwd))
+            .and_then(|manifest| {
+                Ok(complete_enabled_features(
+                    &manifest,
+                    &args.dependency,
+                    &args.input,
+                )?)
+            }),
+        _ => return None,
+    })
+}
+
+/// Complete a mode completing from the index.
+fn complete_indexed<'a>(
+    mode: Mode,
+    index: &'a dyn IndexBackend,
+    config: &'a CargoConfig,
+    options: &'a Options,
+) -> Result<Box<dyn Iterator<Item = Candidate> + 'a>> {
+    Ok(match mode {
+        Mode::Crate(s) => complete_crate_described_iter(index, s.input.as_str(), options)?,
+        Mode::Feature(s) => {
+            // `name[@version][:partial]`
+            let (spec, partial) = match &s.partial {
+                Some(partial) => (s.input.as_str(), partial.as_str()),
+                None => s.input.rsplit_once(':').unwrap_or((&s.input, "")),
+            };
+            let (name, ver) = spec.split_once('@').unwrap_or((spec, ""));
+            Box::new(complete_feature_ranked(index, name, ver, partial, options)?.into_iter())
+        }
+        Mode::Upgrade(args) => Box::new(
+            complete_upgradable(
+                &manifest_or_current(&args.manifest_path, config.cwd())?,
+                args.latest.then_some(index),
+                &args.input,
+                options,
+            )?
+            .into_iter(),
+        ),
+        Mode::Line(args) => Box::new(
+            complete_line(
+                index,
+                config,
+                options,
+                cut_at(&args.line, args.point.unwrap_or(usize::MAX)),
+            )?
+            .into_iter(),
+        ),
+        mode => match complete_standalone(&mode) {
+            Some(candidates) => Box::new(candidates?.into_iter()),
+            None => return Err(anyhow!("not a completion query")),
+        },
+    })
+}
+
+/// Bound the threads walking the index by `--jobs`, or else by `jobs` in the settings.
+fn limit_jobs(opts: &Opts) -> Result<()> {
+    if let Some(jobs) = opts.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()?;
+    }
+    Ok(())
+}
+
+/// When to stop searching for candidates, counting from now.
+fn deadline(opts: &Opts) -> Option<Deadline> {
+    opts.timeout_ms
+        .map(|timeout| Deadline::after(Duration::from_millis(timeout)))
+}
+
+/// Answer each line of stdin as a query, a failing one getting no candidates.
+fn batch(
+    opts: &Opts,
+    index: &dyn IndexBackend,
+    config: &CargoConfig,
+    options: &mut Options,
+    output: &mut OutputOptions,
+) -> Result<()> {
+    let terminator = if output.print0 { '\0' } else { '\n' };
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        // Each query gets the whole time.
+        options.deadline = deadline(opts);
+        output.deadline = options.deadline.clone();
+        let words = iter::once(env!("CARGO_PKG_NAME")).chain(split_words(&line));
+        let candidates = Mode::try_parse_from(words)
+            .map_err(Into::into)
+            .and_then(|mode| complete_indexed(mode, index, config, options))
+            .unwrap_or_else(|_| Box::new(iter::empty()));
+        print(output, candidates)?;
+        output::end_block(output.format, &mut io::stdout().lock(), terminator)?;
+    }
+    Ok(())
+}
+
+/// Tells the index the options select apart from others.
+fn index_key(opts: &Opts, config: &CargoConfig) -> String {
+    let registry = opts
+        .registry
+        .as_deref()
+        .unwrap_or_else(|| config.default_registry());
+    format!(
+        "{:?}",
+        (
+            &opts.index_path,
+            registry,
+            config.resolve_source(registry).ok(),
+            opts.all_registries,
+            opts.fetch,
+            opts.online,
+        )
+    )
+}
 
-    println!(
-        "{}",
-        match opts.mode {
-            Mode::Crate(s) => complete_crate(&index, s.input.as_str())?
-                .into_iter()
-                .join("\n"),
-            Mode::Feature(s) => {
-                let (name, ver) = s.input.split_once('@').unwrap();
-                complete_feature(&index, name, ver)?.join("\n")
+/// Open the index the options select, using what `warm` cached of it.
+fn open_index(opts: &Opts, config: &CargoConfig) -> Result<Box<dyn IndexBackend>> {
+    Ok(Box::new(PersistentIndex::new(
+        open_registry_index(opts, config)?,
+        &index_key(opts, config),
+    )))
+}
+
+/// Go offline if the options or cargo's configuration ask to, reach none of the services the
+/// settings disable, and use the proxy cargo would.
+fn set_network_policy(opts: &Opts, config: &CargoConfig) {
+    net::set_offline(opts.offline || config.net_offline());
+    net::set_disabled(opts.disabled.clone());
+    net::set_proxy(config.get_str(&["http", "proxy"]).map(str::to_string));
+}
+
+/// Open the index the options select as is.
+fn open_registry_index(opts: &Opts, config: &CargoConfig) -> Result<Box<dyn IndexBackend>> {
+    // Completing from what is at hand beats failing on what can't be fetched.
+    let fetch = opts.fetch && net::allowed(Service::Sparse);
+    let mut index = match (&opts.index_path, &opts.registry) {
+        (Some(path), _) => backend::detect(path.clone(), fetch),
+        (None, Some(name)) => backend::registry(config, name, fetch)?,
+        (None, None) if opts.all_registries => Box::new(MultiIndex::discover(fetch)?),
+        (None, None) => backend::default_registry(config, fetch)?,
+    };
+    if opts.online && net::allowed(Service::Api) {
+        index = backend::with_online_fallback(index);
+    }
+    Ok(index)
+}
+
+/// Completion options of the command line.
+fn options(opts: &Opts, config: &CargoConfig) -> Result<Options> {
+    Ok(Options {
+        include_prerelease: opts.include_prerelease,
+        include_yanked: opts.include_yanked,
+        hide_yanked_crates: opts.hide_yanked_crates,
+        all_versions: opts.all_versions,
+        rustc: opts.msrv.and_then(|_| rustc_version()),
+        hide_incompatible: opts.msrv == Some(Msrv::Hide),
+        max_edition: match &opts.max_edition {
+            Some(Some(edition)) => Some(edition.clone()),
+            Some(None) => find_manifest(config.cwd())
+                .and_then(|manifest| package_edition(&manifest))
+                .or_else(|| Some("2015".to_string())),
+            None => None,
+        },
+        advisory_db: if opts.advisories || opts.no_vulnerable || opts.advisory_db.is_some() {
+            opts.advisory_db
+                .clone()
+                .or_else(default_db)
+                .map(AdvisoryDb::open)
+        } else {
+            None
+        },
+        hide_vulnerable: opts.no_vulnerable,
+        license_policy: opts.license_allow.as_deref().map(LicensePolicy::new),
+        rank_by_downloads: opts.rank_by_downloads,
+        dump: Dump::open_default(),
+        match_mode: opts.match_mode.unwrap_or(Match::Prefix).into(),
+        case: opts.case.unwrap_or(Case::Sensitive).into(),
+        history: (!opts.no_history).then(History::load),
+        crate_filter: CrateFilter::new(&opts.deny, &opts.allow)?,
+        boost_prefixes: opts.boost_prefixes.clone(),
+        weights: opts.weights,
+        limit: opts.limit,
+        deadline: deadline(opts),
+    })
+}
+
+/// Variable telling how often `-v` is repeated unless the command line gives it, as clap only
+/// reads options taking a value or single flags from the environment.
+const VERBOSE_VAR: &str = "CARGO_EDIT_COMPLETION_VERBOSE";
+
+/// Log to stderr at the level `-v` or [`VERBOSE_VAR`] asks for, or as `RUST_LOG` filters.
+fn init_logging(verbose: u64) {
+    let verbose = match verbose {
+        0 => env::var(VERBOSE_VAR)
+            .ok()
+            .and_then(|count| count.trim().parse().ok())
+            .unwrap_or(0),
+        verbose => verbose,
+    };
+    let level = match verbose {
+        0 => "off",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
+
+/// Exit codes telling apart why completing failed, for scripts and shell plugins.
+const EXIT_FAILURE: i32 = 1;
+/// The command line or the typed text couldn't be parsed.
+const EXIT_USAGE: i32 = 2;
+/// No registry index could be opened.
+const EXIT_NO_INDEX: i32 = 3;
+/// The crate to complete the versions or features of isn't in the index.
+const EXIT_CRATE_NOT_FOUND: i32 = 4;
+
+/// Failure to open any registry index, telling the cause along with it.
+#[derive(Debug)]
+struct NoIndex;
+
+impl fmt::Display for NoIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no registry index to complete from")
+    }
+}
+
+fn exit_code(err: &anyhow::Error) -> i32 {
+    if err.downcast_ref::<NoIndex>().is_some() {
+        return EXIT_NO_INDEX;
+    }
+    for cause in err.chain() {
+        match cause.downcast_ref::<Error>() {
+            Some(Error::IndexMissing(_)) => return EXIT_NO_INDEX,
+            Some(Error::CrateNotFound(_)) => return EXIT_CRATE_NOT_FOUND,
+            Some(Error::VersionParse(_) | Error::Pattern(_)) => return EXIT_USAGE,
+            _ if cause.is::<clap::Error>() => return EXIT_USAGE,
+            _ => {}
+        }
+    }
+    EXIT_FAILURE
+}
+
+fn entry(mut opts: Opts) -> Result<()> {
+    let start = Instant::now();
+    init_logging(opts.verbose);
+    let config = CargoConfig::load_default()?;
+    Settings::load(opts.config.as_deref(), &config)?
+        .apply(&mut opts, env::var_os(CACHE_DIR_VAR).as_deref());
+    cache::set_cache_dir(opts.cache_dir.clone());
+    info!(elapsed = ?start.elapsed(), "loaded config");
+    let mut output_options = OutputOptions {
+        format: opts.format,
+        limit: opts.limit,
+        print0: opts.print0,
+        deadline: deadline(&opts),
+    };
+    let mode = match opts.mode.take() {
+        Some(mode) => Some(mode),
+        None if opts.stdin => None,
+        None => return Err(anyhow!("no mode given")),
+    };
+    if let Some(Mode::InstallCompletions(args)) = &mode {
+        return install::install(args.shell, args.print);
+    }
+    if let Some(Mode::Record(args)) = &mode {
+        return Ok(history::record(&history::picks(&args.line))?);
+    }
+    if let Some(Mode::ImportDump(args)) = &mode {
+        set_network_policy(&opts, &config);
+        let path = dump::default_path().ok_or_else(|| anyhow!("no cache directory"))?;
+        let count = match &args.archive {
+            Some(archive) => dump::import(File::open(archive)?, &path)?,
+            None => dump::import(dump::download()?, &path)?,
+        };
+        eprintln!("imported {} crates into {}", count, path.display());
+        return Ok(());
+    }
+    let socket = opts
+        .socket
+        .clone()
+        .or_else(daemon::default_socket)
+        .ok_or_else(|| anyhow!("no directory for the daemon socket"))?;
+    if let Some(Mode::Daemon) = &mode {
+        limit_jobs(&opts)?;
+        return daemon::run(&socket);
+    }
+    if !opts.stdin {
+        if let Some(candidates) = mode.as_ref().and_then(complete_standalone) {
+            return print(&output_options, candidates?.into_iter());
+        }
+        let indexed = matches!(
+            mode,
+            Some(Mode::Crate(_) | Mode::Feature(_) | Mode::Upgrade(_) | Mode::Line(_))
+        );
+        if indexed && !opts.no_daemon {
+            if let Some(candidates) = daemon::query(&socket, output_options.deadline.as_ref()) {
+                return print(&output_options, candidates.into_iter());
             }
         }
+    }
+    set_network_policy(&opts, &config);
+    limit_jobs(&opts)?;
+    if let Some(Mode::Warm) = &mode {
+        let index = open_registry_index(&opts, &config).context(NoIndex)?;
+        let (names, crates) = persist::warm(
+            &*index,
+            &index_key(&opts, &config),
+            Dump::open_default().as_ref(),
+        )?;
+        eprintln!(
+            "cached {} crate names and the versions of {} crates",
+            names, crates
+        );
+        return Ok(());
+    }
+    if let Some(Mode::Doctor) = &mode {
+        return doctor::doctor(&opts, &config);
+    }
+    if let Some(Mode::Stats) = &mode {
+        return stats::stats(&config, opts.index_path.as_deref());
+    }
+    if let Some(Mode::Bench(args)) = &mode {
+        return bench::bench(&opts, &config, &args.queries, args.runs);
+    }
+    let index = open_index(&opts, &config).context(NoIndex)?;
+    let mut options = options(&opts, &config)?;
+    // Counting from the start, and telling the output when the search was cut short.
+    options.deadline = output_options.deadline.clone();
+    info!(elapsed = ?start.elapsed(), "opened index");
+
+    let mode = match mode {
+        Some(Mode::Serve(args)) if !opts.stdin => {
+            if !args.stdio {
+                return Err(anyhow!("no transport given, e.g. --stdio"));
+            }
+            return serve::serve(
+                &opts,
+                io::stdin().lock(),
+                &mut io::stdout().lock(),
+                &*index,
+                &config,
+                &mut options,
+            );
+        }
+        Some(mode) if !opts.stdin => mode,
+        _ => return batch(&opts, &*index, &config, &mut options, &mut output_options),
+    };
+    if let Mode::Bash = mode {
+        let line = env::var("COMP_LINE")?;
+        let point = env::var("COMP_POINT")
+            .ok()
+            .and_then(|point| point.parse().ok())
+            .unwrap_or(usize::MAX);
+        let line = cut_at(&line, point);
+        // bash splits words at `=` and `:` and only replaces the part after them.
+        let current = split_words(line).pop().unwrap_or_default();
+        let cut = current.rfind(&['=', ':'][..]).map_or(0, |idx| idx + 1);
+        // Candidates not extending what bash keeps can't replace the part after it.
+        let candidates: Vec<_> = complete_line(&*index, &config, &options, line)?
+            .into_iter()
+            .filter_map(|candidate| {
+                let rest = candidate.value.strip_prefix(&current[..cut])?;
+                Some(rest.to_string())
+            })
+            .take(opts.limit.unwrap_or(usize::MAX))
+            .collect();
+        println!("{}", candidates.join("\n"));
+        return Ok(());
+    }
+    let candidates = complete_indexed(mode, &*index, &config, &options)?;
+    print(&output_options, candidates)?;
+    info!(
+        elapsed = ?start.elapsed(),
+        parsed_files = crates::parsed_files(),
+        skipped_entries = crates::skipped_entries(),
+        "completed"
     );
     Ok(())
 }
@@ -51,6 +869,32 @@ fn entry() -> Result<()> {
 // cargo_edit_completion crate tracing_test -> tracing-test, tracing-test-macro
 // cargo_edit_completion crate actix-web@3 -> actix-web@3.3.2, actix-web@3.3.1, ...
 
+/// Whether `--quiet` was asked for, telling even before the command line parsed.
+fn quiet_requested() -> bool {
+    env::args().any(|arg| arg == "--quiet" || arg == "-q")
+        || env::var("CARGO_EDIT_COMPLETION_QUIET")
+            .is_ok_and(|quiet| !matches!(quiet.as_str(), "" | "0" | "false" | "no" | "off"))
+}
+
 fn main() {
-    drop(entry())   // ignore all errors
+    let opts = match Opts::try_parse() {
+        Ok(opts) => opts,
+        // `--help` and `--version` print to stdout, and parse errors only unless `--quiet`.
+        Err(err) if !err.use_stderr() || !quiet_requested() => err.exit(),
+        Err(_) => process::exit(EXIT_USAGE),
+    };
+    let quiet = opts.quiet;
+    if let Err(err) = entry(opts) {
+        // The shell stopped reading, e.g. because another key was typed.
+        if err
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+        {
+            return;
+        }
+        if !quiet {
+            eprintln!("{}: {:#}", env!("CARGO_BIN_NAME"), err);
+        }
+        process::exit(exit_code(&err));
+    }
 }
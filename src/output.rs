@@ -0,0 +1,168 @@
+use std::io::{self, Write};
+
+use clap::ArgEnum;
+use serde_json::{json, Value};
+
+use cargo_edit_completion_lib::deadline::Deadline;
+use cargo_edit_completion_lib::Candidate;
+
+#[derive(ArgEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    /// One candidate per line
+    Plain,
+    /// `value:description` pairs for zsh's `_describe`
+    Zsh,
+    /// `value<TAB>description` lines for fish's `complete -a`
+    Fish,
+    /// `completionText<TAB>listItemText<TAB>toolTip` triples for PowerShell's `CompletionResult`
+    Powershell,
+    /// JSON objects with `value` and `display`, one per line, for elvish's `edit:complex-candidate`
+    Elvish,
+    /// A JSON array of records with `value` and `description` for nushell's external completer
+    Nushell,
+    /// A JSON array of records with `schema`, `value`, `display`, `description`, `kind`, `score`
+    /// and `yanked`, for editor plugins and scripts
+    Json,
+    /// The records of `json` as a stream of MessagePack maps, one per candidate, for binary
+    /// consumers
+    Msgpack,
+}
+
+/// Version of the records of `json` and `msgpack`, told in their `schema` field.
+///
+/// It is only bumped when a field is removed, renamed or changes meaning; fields may be added
+/// without a bump, so consumers should ignore the ones they do not know.
+const SCHEMA: u32 = 1;
+
+fn escape_zsh(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(':', "\\:")
+}
+
+fn display(candidate: &Candidate) -> String {
+    match &candidate.description {
+        Some(description) => format!("{} ({})", candidate.label(), description),
+        None => candidate.label().to_string(),
+    }
+}
+
+/// Render a candidate as a single line, for line-oriented formats.
+fn line(format: Format, candidate: &Candidate) -> String {
+    match (format, &candidate.description) {
+        (Format::Zsh, Some(description)) => {
+            format!("{}:{}", escape_zsh(&candidate.value), description)
+        }
+        (Format::Zsh, None) => escape_zsh(&candidate.value),
+        (Format::Fish, Some(description)) => {
+            format!("{}\t{}", candidate.value, description.replace('\t', " "))
+        }
+        (Format::Powershell, description) => format!(
+            "{}\t{}\t{}",
+            candidate.value,
+            candidate.value,
+            // PowerShell rejects empty tooltips
+            description.as_deref().unwrap_or(&candidate.value)
+        ),
+        (Format::Elvish, _) => {
+            json!({"value": candidate.value, "display": display(candidate)}).to_string()
+        }
+        _ => candidate.value.clone(),
+    }
+}
+
+/// Render a candidate as a record, for formats made of them.
+pub fn record(format: Format, candidate: &Candidate) -> Option<Value> {
+    match format {
+        Format::Nushell => {
+            Some(json!({"value": candidate.value, "description": candidate.description}))
+        }
+        Format::Json | Format::Msgpack => Some(json!({
+            "schema": SCHEMA,
+            "value": candidate.value,
+            "display": candidate.label(),
+            "description": candidate.description,
+            "kind": candidate.kind.as_str(),
+            "score": candidate.score,
+            "yanked": candidate.yanked,
+        })),
+        _ => None,
+    }
+}
+
+/// Told along with the last candidate when `--limit` left out others.
+const MORE: &str = "more not shown";
+/// Told along with the last candidate when `--timeout-ms` cut the search short.
+const PARTIAL: &str = "search timed out";
+
+fn note(candidate: &mut Candidate, note: &str) {
+    candidate.description = Some(match &candidate.description {
+        Some(description) => format!("{}; {}", description, note),
+        None => note.to_string(),
+    });
+}
+
+/// Write candidates as they come, flushing after each so shells can show the first ones early.
+///
+/// Only the first `limit` candidates are written; formats with descriptions note with the last
+/// one if more were left out, or if the `deadline` cut the search short, which `json` and
+/// `msgpack` also tell by `"partial": true`. Each is ended by `terminator`, e.g. a NUL for
+/// `xargs -0`.
+pub fn write(
+    format: Format,
+    out: &mut impl Write,
+    candidates: impl Iterator<Item = Candidate>,
+    limit: Option<usize>,
+    deadline: Option<&Deadline>,
+    terminator: char,
+) -> io::Result<()> {
+    let mut candidates = candidates.peekable();
+    let limit = limit.unwrap_or(usize::MAX);
+    let array = matches!(format, Format::Nushell | Format::Json);
+    if array {
+        write!(out, "[")?;
+    }
+    let mut count = 0;
+    while count < limit {
+        let mut candidate = match candidates.next() {
+            Some(candidate) => candidate,
+            None => break,
+        };
+        count += 1;
+        let last = candidates.peek().is_none();
+        let partial = last && deadline.is_some_and(Deadline::cut_short);
+        if format != Format::Plain {
+            if count == limit && !last {
+                note(&mut candidate, MORE);
+            } else if partial {
+                note(&mut candidate, PARTIAL);
+            }
+        }
+        let mut record = record(format, &candidate);
+        if let Some(Value::Object(fields)) = &mut record {
+            if partial && fields.contains_key("schema") {
+                fields.insert("partial".to_string(), Value::Bool(true));
+            }
+        }
+        match record {
+            Some(record) if format == Format::Msgpack => {
+                rmp_serde::encode::write_named(out, &record).map_err(io::Error::other)?
+            }
+            Some(record) => write!(out, "{}{}", if count > 1 { "," } else { "" }, record)?,
+            None => write!(out, "{}{}", line(format, &candidate), terminator)?,
+        }
+        out.flush()?;
+    }
+    if array {
+        write!(out, "]{}", terminator)?;
+    }
+    out.flush()
+}
+
+/// End the candidates answering one query of a batch, for formats not delimiting them already.
+pub fn end_block(format: Format, out: &mut impl Write, terminator: char) -> io::Result<()> {
+    match format {
+        Format::Nushell | Format::Json => {}
+        Format::Msgpack => rmp_serde::encode::write(out, &()).map_err(io::Error::other)?,
+        _ => write!(out, "{}", terminator)?,
+    }
+    out.flush()
+}
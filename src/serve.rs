@@ -0,0 +1,101 @@
+use std::io::{BufRead, Write};
+use std::iter;
+
+use anyhow::Result;
+use clap::Parser;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use cargo_edit_completion_lib::backend::IndexBackend;
+use cargo_edit_completion_lib::config::CargoConfig;
+use cargo_edit_completion_lib::line::split_words;
+use cargo_edit_completion_lib::Options;
+
+use crate::output::{self, Format};
+use crate::{complete_indexed, deadline, Line, Mode, Opts};
+
+/// A query of an editor, e.g. `{"id": 1, "kind": "crate", "input": "tok"}`.
+#[derive(Deserialize)]
+struct Request {
+    /// Echoed in the response to tell which request it answers.
+    #[serde(default)]
+    id: Value,
+    /// A mode, e.g. `crate`, `feature` or `line`.
+    kind: String,
+    /// The arguments of the mode, or the command line for `line`.
+    #[serde(default)]
+    input: String,
+    /// Cursor offset in characters into the command line for `line`.
+    point: Option<usize>,
+}
+
+fn mode(request: &Request) -> Result<Mode> {
+    if request.kind == "line" {
+        return Ok(Mode::Line(Line {
+            line: request.input.clone(),
+            point: request.point,
+        }));
+    }
+    let words = iter::once(env!("CARGO_PKG_NAME"))
+        .chain(iter::once(request.kind.as_str()))
+        .chain(split_words(&request.input));
+    Ok(Mode::try_parse_from(words)?)
+}
+
+fn respond(
+    line: &str,
+    index: &dyn IndexBackend,
+    config: &CargoConfig,
+    options: &Options,
+    limit: Option<usize>,
+) -> Value {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => return json!({"id": null, "error": err.to_string()}),
+    };
+    let candidates = mode(&request).and_then(|mode| {
+        Ok(complete_indexed(mode, index, config, options)?
+            .take(limit.unwrap_or(usize::MAX))
+            .filter_map(|candidate| output::record(Format::Json, &candidate))
+            .collect::<Vec<_>>())
+    });
+    let partial = options
+        .deadline
+        .as_ref()
+        .is_some_and(|deadline| deadline.cut_short());
+    match candidates {
+        Ok(candidates) => {
+            json!({"id": request.id, "candidates": candidates, "partial": partial})
+        }
+        Err(err) => json!({"id": request.id, "error": err.to_string()}),
+    }
+}
+
+/// Answer newline-delimited JSON requests with a JSON line each, until the input ends.
+///
+/// A response has the `id` of its request and either the `json` records of the `candidates`,
+/// with `partial` telling whether `--timeout-ms` cut the search short, or an `error` message.
+pub fn serve(
+    opts: &Opts,
+    input: impl BufRead,
+    out: &mut impl Write,
+    index: &dyn IndexBackend,
+    config: &CargoConfig,
+    options: &mut Options,
+) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Each request gets the whole time.
+        options.deadline = deadline(opts);
+        writeln!(
+            out,
+            "{}",
+            respond(&line, index, config, options, opts.limit)
+        )?;
+        out.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use toml::Value;
+
+use cargo_edit_completion_lib::config::CargoConfig;
+use cargo_edit_completion_lib::filter::CONFIG_TABLE;
+use cargo_edit_completion_lib::net::Service;
+use cargo_edit_completion_lib::Weights;
+
+use crate::{Case, Match, Opts};
+
+/// Defaults of the command line options, keyed by their long names, e.g.
+///
+/// ```toml
+/// match = "fuzzy"
+/// limit = 20
+/// include-prerelease = true
+/// ```
+///
+/// Read from the settings file and the `[cargo-edit-completion]` table of cargo's configuration,
+/// which takes precedence. Options given on the command line take precedence over both; flags can
+/// only be turned on.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Settings {
+    #[serde(rename = "match")]
+    match_mode: Option<Match>,
+    case: Option<Case>,
+    limit: Option<usize>,
+    timeout_ms: Option<u64>,
+    include_prerelease: Option<bool>,
+    include_yanked: Option<bool>,
+    hide_yanked_crates: Option<bool>,
+    all_versions: Option<bool>,
+    rank_by_downloads: Option<bool>,
+    no_history: Option<bool>,
+    registry: Option<String>,
+    all_registries: Option<bool>,
+    index_path: Option<PathBuf>,
+    fetch: Option<bool>,
+    online: Option<bool>,
+    offline: Option<bool>,
+    jobs: Option<usize>,
+    /// Services to reach over the network or not, e.g. `backends.api = false`; all are by default.
+    backends: HashMap<Service, bool>,
+    /// Directory for the caches instead of `$XDG_CACHE_HOME/cargo-edit-completion`, overridden
+    /// by [`CACHE_DIR_VAR`].
+    cache_dir: Option<PathBuf>,
+    /// Patterns of crates to always hide, matching whole names.
+    deny: Vec<String>,
+    /// Patterns of crates to always show.
+    allow: Vec<String>,
+    /// Prefixes of crates to list before all others.
+    boost_prefixes: Vec<String>,
+    /// How much each signal counts when ranking crate names, e.g. `weights.history = 2.0`.
+    weights: Option<Weights>,
+}
+
+/// Variable naming the directory for the caches.
+pub const CACHE_DIR_VAR: &str = "CARGO_EDIT_COMPLETION_CACHE_DIR";
+
+/// Where the settings are read from unless `--config` names another file.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+}
+
+impl Settings {
+    /// Read the settings file and the `[cargo-edit-completion]` table of `config`, which takes
+    /// precedence.
+    pub fn load(path: Option<&Path>, config: &CargoConfig) -> Result<Self> {
+        let cargo: Self = Value::Table(config.table(&[CONFIG_TABLE]))
+            .try_into()
+            .with_context(|| {
+                format!("invalid [{}] table in cargo's configuration", CONFIG_TABLE)
+            })?;
+        Ok(cargo.or(Self::load_file(path)?))
+    }
+
+    /// Read the settings file, an absent default one meaning no settings.
+    fn load_file(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let content = match fs::read_to_string(&path) {
+            Err(err) if !required && err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            content => content.with_context(|| format!("failed to read {}", path.display()))?,
+        };
+        toml::from_str(&content).with_context(|| format!("invalid settings in {}", path.display()))
+    }
+
+    /// These settings, falling back to `other` for the ones left unset. Lists of both are kept.
+    fn or(self, other: Self) -> Self {
+        let concat = |mut first: Vec<String>, second: Vec<String>| {
+            first.extend(second);
+            first
+        };
+        let mut backends = other.backends;
+        backends.extend(self.backends);
+        Self {
+            match_mode: self.match_mode.or(other.match_mode),
+            case: self.case.or(other.case),
+            limit: self.limit.or(other.limit),
+            timeout_ms: self.timeout_ms.or(other.timeout_ms),
+            include_prerelease: self.include_prerelease.or(other.include_prerelease),
+            include_yanked: self.include_yanked.or(other.include_yanked),
+            hide_yanked_crates: self.hide_yanked_crates.or(other.hide_yanked_crates),
+            all_versions: self.all_versions.or(other.all_versions),
+            rank_by_downloads: self.rank_by_downloads.or(other.rank_by_downloads),
+            no_history: self.no_history.or(other.no_history),
+            registry: self.registry.or(other.registry),
+            all_registries: self.all_registries.or(other.all_registries),
+            index_path: self.index_path.or(other.index_path),
+            fetch: self.fetch.or(other.fetch),
+            online: self.online.or(other.online),
+            offline: self.offline.or(other.offline),
+            jobs: self.jobs.or(other.jobs),
+            backends,
+            cache_dir: self.cache_dir.or(other.cache_dir),
+            deny: concat(self.deny, other.deny),
+            allow: concat(self.allow, other.allow),
+            boost_prefixes: concat(self.boost_prefixes, other.boost_prefixes),
+            weights: self.weights.or(other.weights),
+        }
+    }
+
+    /// Fill in the options the command line left unset. `cache_dir` is the value of
+    /// [`CACHE_DIR_VAR`], which takes precedence over the setting.
+    pub fn apply(self, opts: &mut Opts, cache_dir: Option<&OsStr>) {
+        opts.match_mode = opts.match_mode.or(self.match_mode);
+        opts.case = opts.case.or(self.case);
+        opts.limit = opts.limit.or(self.limit);
+        opts.timeout_ms = opts.timeout_ms.or(self.timeout_ms);
+        opts.include_prerelease |= self.include_prerelease.unwrap_or_default();
+        opts.include_yanked |= self.include_yanked.unwrap_or_default();
+        opts.hide_yanked_crates |= self.hide_yanked_crates.unwrap_or_default();
+        opts.all_versions |= self.all_versions.unwrap_or_default();
+        opts.rank_by_downloads |= self.rank_by_downloads.unwrap_or_default();
+        opts.no_history |= self.no_history.unwrap_or_default();
+        // The command line picking an index overrides the one of the settings.
+        if opts.index_path.is_none() && opts.registry.is_none() && !opts.all_registries {
+            opts.index_path = self.index_path;
+            opts.registry = self.registry;
+            opts.all_registries = self.all_registries.unwrap_or_default();
+        }
+        opts.fetch |= self.fetch.unwrap_or_default();
+        opts.online |= self.online.unwrap_or_default();
+        opts.offline |= self.offline.unwrap_or_default();
+        opts.jobs = opts.jobs.or(self.jobs);
+        opts.disabled = self
+            .backends
+            .into_iter()
+            .filter(|(_, enabled)| !enabled)
+            .map(|(service, _)| service)
+            .collect();
+        let cache_dir = cache_dir.filter(|dir| !dir.is_empty()).map(PathBuf::from);
+        opts.cache_dir = cache_dir.or(self.cache_dir);
+        opts.deny = self.deny;
+        opts.allow = self.allow;
+        opts.boost_prefixes = self.boost_prefixes;
+        opts.weights = self.weights;
+    }
+}
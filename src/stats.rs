@@ -0,0 +1,192 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use cargo_edit_completion_lib::backend::{self, IndexBackend};
+use cargo_edit_completion_lib::cache::cache_dir;
+use cargo_edit_completion_lib::config::{self, CargoConfig, Source, CRATES_IO};
+use cargo_edit_completion_lib::crates::index_root;
+use cargo_edit_completion_lib::error;
+
+/// How many of the largest files of an index to list.
+const LARGEST: usize = 5;
+
+/// A file found under a directory.
+struct File {
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Files under a directory, leaving out git objects.
+fn files(dir: &Path) -> Vec<File> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let meta = match entry.metadata() {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            if meta.is_dir() {
+                if entry.file_name() != ".git" {
+                    dirs.push(entry.path());
+                }
+            } else {
+                files.push(File {
+                    path: entry.path(),
+                    size: meta.len(),
+                    modified: meta.modified().ok(),
+                });
+            }
+        }
+    }
+    files
+}
+
+fn size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+fn age(time: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    match secs {
+        0..=119 => format!("{} seconds ago", secs),
+        120..=7199 => format!("{} minutes ago", secs / 60),
+        7200..=172_799 => format!("{} hours ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+/// Where a registry's index lives and what kind it is, `None` if it was never fetched.
+pub fn location(config: &CargoConfig, name: &str) -> Result<(&'static str, Option<PathBuf>)> {
+    Ok(match config.resolve_source(name)? {
+        Source::Registry(url) => (
+            if url.starts_with("sparse+") {
+                "sparse"
+            } else {
+                "git"
+            },
+            config::index_dir(&index_root()?, &url),
+        ),
+        Source::LocalRegistry(path) => ("local registry", Some(path.join("index"))),
+        Source::Directory(path) => ("directory", Some(path)),
+    })
+}
+
+fn print_index(
+    name: &str,
+    kind: &str,
+    path: Option<&Path>,
+    index: error::Result<Box<dyn IndexBackend>>,
+) {
+    println!("{} ({})", name, kind);
+    let path = match path {
+        Some(path) => path,
+        None => {
+            println!("  not fetched yet");
+            return;
+        }
+    };
+    println!("  path     {}", path.display());
+    match index.and_then(|index| index.crates_with_prefix("")) {
+        Ok(crates) => println!("  crates   {}", crates.len()),
+        Err(err) => println!("  crates   unreadable: {}", err),
+    }
+    let files = files(path);
+    println!(
+        "  size     {}",
+        size(files.iter().map(|file| file.size).sum())
+    );
+    if let Some(modified) = files.iter().filter_map(|file| file.modified).max() {
+        println!("  updated  {}", age(modified));
+    }
+    let largest = files
+        .iter()
+        .sorted_by_key(|file| Reverse(file.size))
+        .take(LARGEST)
+        .map(|file| {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            format!("{} ({})", name, size(file.size))
+        })
+        .join(", ");
+    if !largest.is_empty() {
+        println!("  largest  {}", largest);
+    }
+}
+
+/// Print what the completer sees: the configured registries, how many crates their indexes hold
+/// and their largest files, and how much the caches take.
+pub fn stats(config: &CargoConfig, index_path: Option<&Path>) -> Result<()> {
+    match index_path {
+        Some(path) => print_index(
+            "--index-path",
+            "index",
+            Some(path),
+            Ok(backend::detect(path.to_path_buf(), false)),
+        ),
+        None => {
+            let names: Vec<String> = [CRATES_IO.to_string()]
+                .into_iter()
+                .chain(config.registry_names())
+                .dedup()
+                .collect();
+            println!("registries {}", names.join(", "));
+            for name in &names {
+                match location(config, name) {
+                    Ok((kind, path)) => print_index(
+                        name,
+                        kind,
+                        path.as_deref(),
+                        backend::registry(config, name, false),
+                    ),
+                    Err(err) => println!("{}: {}", name, err),
+                }
+            }
+        }
+    }
+    if let Some(dir) = cache_dir() {
+        let files = files(&dir);
+        println!("caches ({})", dir.display());
+        println!(
+            "  size     {}",
+            size(files.iter().map(|file| file.size).sum())
+        );
+        let by_entry = files.iter().into_group_map_by(|file| {
+            file.path
+                .strip_prefix(&dir)
+                .ok()
+                .and_then(|path| path.components().next())
+                .map(|part| part.as_os_str().to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        for (entry, files) in by_entry.into_iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+            let modified = files.iter().filter_map(|file| file.modified).max();
+            println!(
+                "  {:<24} {:>10}  {}",
+                entry,
+                size(files.iter().map(|file| file.size).sum()),
+                modified.map(age).unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+/// Lines printed by completing with `args`, away from the settings, caches and history of the
+/// user running the tests.
+fn complete(args: &[&str]) -> Vec<String> {
+    complete_in(Path::new(env!("CARGO_MANIFEST_DIR")), args)
+}
+
+/// Like [`complete`], run in `dir`.
+fn complete_in(dir: &Path, args: &[&str]) -> Vec<String> {
+    let home = Path::new(env!("CARGO_TARGET_TMPDIR")).join("home");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cargo_edit_completion"));
+    for (key, _) in env::vars() {
+        if key.starts_with("CARGO_EDIT_COMPLETION_") || key.starts_with("XDG_") {
+            command.env_remove(key);
+        }
+    }
+    let output = command
+        .env("HOME", &home)
+        .current_dir(dir)
+        .arg("--no-daemon")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    stdout.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn checked_out_index() {
+    let index = fixture("index");
+    let index = index.to_str().unwrap();
+    assert_eq!(complete(&["--index-path", index, "crate", "se"]), ["serde"]);
+    assert_eq!(
+        complete(&["--index-path", index, "crate", "serde@"]),
+        ["serde@1.1.0", "serde@1.0.0"]
+    );
+}
+
+#[test]
+fn limit_keeps_the_best_matches() {
+    let index = fixture("index");
+    let index = index.to_str().unwrap();
+    assert_eq!(
+        complete(&["--index-path", index, "--limit", "2", "crate", "ab"]),
+        ["ab", "abc"]
+    );
+}
+
+#[test]
+fn settings_and_cargo_config_alike() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("settings");
+    fs::create_dir_all(dir.join(".cargo")).unwrap();
+    fs::write(
+        dir.join(".cargo").join("config.toml"),
+        "[cargo-edit-completion]\ndeny = [\"abc\"]\nlimit = 3\n",
+    )
+    .unwrap();
+    let settings = dir.join("settings.toml");
+    fs::write(&settings, "deny = [\"ab-cd\"]\nlimit = 1\n").unwrap();
+    let index = fixture("index");
+    let args = [
+        "--config",
+        settings.to_str().unwrap(),
+        "--index-path",
+        index.to_str().unwrap(),
+        "crate",
+        "a",
+    ];
+    // Both deny lists apply, and cargo's configuration wins over the settings file.
+    assert_eq!(complete_in(&dir, &args), ["a", "ab", "abcd"]);
+}
@@ -0,0 +1,47 @@
+use std::path::{Path, PathBuf};
+
+use cargo_edit_completion_lib::backend::IndexBackend;
+use cargo_edit_completion_lib::crates::CratesIndex;
+
+/// An index holding `a`, `ab` and `abc` in the buckets of short names, and `abcd`, `ab-cd` and
+/// `serde` in those named after their first characters.
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/index")
+}
+
+fn names(prefix: &str) -> Vec<String> {
+    let index = CratesIndex::new(fixture());
+    let mut names = IndexBackend::crates_with_prefix(&index, prefix).unwrap();
+    names.sort();
+    names
+}
+
+#[test]
+fn short_names_match_short_prefixes() {
+    assert_eq!(names("a"), ["a", "ab", "ab-cd", "abc", "abcd"]);
+    assert_eq!(names("ab"), ["ab", "ab-cd", "abc", "abcd"]);
+    assert_eq!(names("abc"), ["abc", "abcd"]);
+    assert_eq!(names("abcd"), ["abcd"]);
+}
+
+#[test]
+fn empty_prefix_matches_all() {
+    assert_eq!(names(""), ["a", "ab", "ab-cd", "abc", "abcd", "serde"]);
+}
+
+#[test]
+fn dashes_and_underscores_alike() {
+    assert_eq!(names("ab_"), ["ab-cd"]);
+    assert_eq!(names("ab-c"), ["ab-cd"]);
+}
+
+#[test]
+fn versions_of_short_names() {
+    let index = CratesIndex::new(fixture());
+    for name in ["a", "ab", "abc", "abcd", "serde"] {
+        let versions = index.versions(name).unwrap().unwrap();
+        let versions: Vec<_> = versions.iter().map(|crate_| &crate_.version).collect();
+        assert_eq!(versions, ["1.0.0", "1.1.0"], "{}", name);
+    }
+    assert!(index.versions("abd").unwrap().is_none());
+}
@@ -0,0 +1,109 @@
+#![cfg(windows)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cargo_edit_completion_lib::backend::IndexBackend;
+use cargo_edit_completion_lib::config;
+use cargo_edit_completion_lib::crates::CratesIndex;
+use cargo_edit_completion_lib::manifest::{complete_member, complete_package_path};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+#[test]
+fn buckets_ignore_case() {
+    let index = CratesIndex::new(fixture("index"));
+    for name in ["SERDE", "Serde", "ABC", "Ab"] {
+        let versions = index.versions(name).unwrap();
+        assert!(
+            versions.is_some_and(|versions| !versions.is_empty()),
+            "{}",
+            name
+        );
+    }
+    let index = CratesIndex::new(fixture("INDEX"));
+    assert_eq!(
+        IndexBackend::crates_with_prefix(&index, "se").unwrap(),
+        ["serde"]
+    );
+}
+
+#[test]
+fn member_directories_use_slashes() {
+    let manifest = fixture("workspace").join("Cargo.toml");
+    let members = complete_member(&manifest, "").unwrap();
+    let members: Vec<_> = members
+        .iter()
+        .map(|member| (member.value.as_str(), member.description.as_deref()))
+        .collect();
+    assert_eq!(
+        members,
+        [("inner", Some("crates/inner")), ("outer", Some("."))]
+    );
+}
+
+#[test]
+fn package_paths_after_backslashes() {
+    let candidates = complete_package_path(&fixture("workspace"), r"crates\in").unwrap();
+    let values: Vec<_> = candidates
+        .iter()
+        .map(|candidate| &candidate.value)
+        .collect();
+    assert_eq!(values, [r"crates\inner/"]);
+    assert_eq!(candidates[0].description.as_deref(), Some("inner"));
+}
+
+#[test]
+fn cargo_home_as_set_in_the_wild() {
+    let base = env::temp_dir();
+    // The only test changing the environment, so none reads it meanwhile.
+    env::set_var("CARGO_EDIT_COMPLETION_TEST_BASE", &base);
+    for var in [
+        r#""%CARGO_EDIT_COMPLETION_TEST_BASE%\cargo""#,
+        "%CARGO_EDIT_COMPLETION_TEST_BASE%\\cargo  ",
+    ] {
+        env::set_var("CARGO_HOME", var);
+        assert_eq!(config::cargo_home().unwrap(), base.join("cargo"), "{}", var);
+    }
+    // Unknown variables are left for the path to tell.
+    env::set_var("CARGO_HOME", r"C:\%CARGO_EDIT_COMPLETION_UNSET%\cargo");
+    assert_eq!(
+        config::cargo_home().unwrap(),
+        Path::new(r"C:\%CARGO_EDIT_COMPLETION_UNSET%\cargo")
+    );
+    env::remove_var("CARGO_HOME");
+}
+
+#[test]
+fn index_beyond_max_path() {
+    // Created in the verbatim form, as other paths this long can't be.
+    let mut root = fs::canonicalize(env::temp_dir()).unwrap();
+    root.push(format!("cargo-edit-completion-{}", std::process::id()));
+    let mut path = root.clone();
+    while path.as_os_str().len() < 300 {
+        path.push("a".repeat(50));
+    }
+    let bucket = path.join("se").join("rd");
+    fs::create_dir_all(&bucket).unwrap();
+    fs::copy(
+        fixture("index").join("se").join("rd").join("serde"),
+        bucket.join("serde"),
+    )
+    .unwrap();
+
+    // Handed over as configured, without the verbatim prefix.
+    let plain = PathBuf::from(path.to_str().unwrap().trim_start_matches(r"\\?\"));
+    let index = CratesIndex::new(plain);
+    assert_eq!(
+        IndexBackend::crates_with_prefix(&index, "ser").unwrap(),
+        ["serde"]
+    );
+    assert!(index.versions("serde").unwrap().is_some());
+    fs::remove_dir_all(root).unwrap();
+}